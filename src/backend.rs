@@ -0,0 +1,326 @@
+//! Which HTTP API the plugin talks to, and translation of Ollama-native
+//! request bodies and responses to and from OpenAI-compatible servers.
+//!
+//! Actions always build a native `/api/*` body; with the `openai-compatible`
+//! backend it is rewritten for `/v1/*` and the reply is rewritten back into
+//! the shape Ollama would have returned, so response handling is shared.
+
+use serde_json::{json, Map, Value};
+
+use crate::error::OllamaError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    OllamaNative,
+    OpenAiCompatible,
+}
+
+impl Backend {
+    pub fn from_config(config: &Value) -> Result<Self, OllamaError> {
+        match config.get("backend").and_then(|v| v.as_str()) {
+            None | Some("ollama-native") => Ok(Backend::OllamaNative),
+            Some("openai-compatible") => Ok(Backend::OpenAiCompatible),
+            Some(other) => Err(OllamaError::bad_request(format!(
+                "unknown backend '{other}' (expected ollama-native or openai-compatible)"
+            ))),
+        }
+    }
+}
+
+/// Native Ollama path for an inference action.
+pub fn native_path(action: &str) -> &'static str {
+    match action {
+        "generate" => "/api/generate",
+        "embeddings" => "/api/embed",
+        _ => "/api/chat",
+    }
+}
+
+/// Path and body for an OpenAI-compatible server, from a native body.
+pub fn to_openai(action: &str, body: &Value) -> Result<(&'static str, Value), OllamaError> {
+    let model = body.get("model").cloned().unwrap_or(Value::Null);
+    if action == "embeddings" {
+        let mut request = json!({
+            "model": model,
+            "input": body["input"],
+            "encoding_format": "float"
+        });
+        if let Some(dimensions) = body.get("dimensions") {
+            request["dimensions"] = dimensions.clone();
+        }
+        return Ok(("/v1/embeddings", request));
+    }
+
+    if body.get("think").is_some() {
+        return Err(unsupported("think"));
+    }
+    let mut request = json!({"model": model, "stream": false});
+    let path = if action == "generate" {
+        if let Some(suffix) = body.get("suffix") {
+            // Fill-in-the-middle needs the legacy completions endpoint.
+            if body.get("images").is_some() {
+                return Err(unsupported("images with suffix"));
+            }
+            request["prompt"] = body["prompt"].clone();
+            request["suffix"] = suffix.clone();
+            "/v1/completions"
+        } else {
+            let user = json!({
+                "role": "user",
+                "content": body["prompt"],
+                "images": body.get("images")
+            });
+            request["messages"] = json!([message(&user)]);
+            "/v1/chat/completions"
+        }
+    } else {
+        let messages = body["messages"].as_array().cloned().unwrap_or_default();
+        request["messages"] = json!(translate_messages(&messages));
+        if let Some(tools) = body.get("tools") {
+            request["tools"] = tools.clone();
+        }
+        "/v1/chat/completions"
+    };
+
+    match body.get("format") {
+        Some(Value::String(_)) => request["response_format"] = json!({"type": "json_object"}),
+        Some(schema @ Value::Object(_)) => {
+            request["response_format"] = json!({
+                "type": "json_schema",
+                "json_schema": {"name": "response", "schema": schema}
+            })
+        }
+        _ => {}
+    }
+    if let Some(options) = body.get("options").and_then(|v| v.as_object()) {
+        for (name, value) in options {
+            match name.as_str() {
+                "temperature" | "top_p" | "top_k" | "seed" | "stop" => {
+                    request[name.as_str()] = value.clone();
+                }
+                "num_predict" => request["max_tokens"] = value.clone(),
+                "repeat_penalty" => request["repetition_penalty"] = value.clone(),
+                // num_ctx is fixed when the server loads the model.
+                _ => {}
+            }
+        }
+    }
+    Ok((path, request))
+}
+
+fn unsupported(what: &str) -> OllamaError {
+    OllamaError::bad_request(format!(
+        "{what} is not supported by the openai-compatible backend"
+    ))
+}
+
+/// OpenAI chat messages from native ones. Ollama has no tool-call ids, so
+/// each call gets one unique across the conversation, and each tool result
+/// carries the id of the call it answers: the earliest unanswered call with
+/// the same `tool_name`, or the earliest unanswered call when unnamed.
+fn translate_messages(native: &[Value]) -> Vec<Value> {
+    let mut next_id = 0;
+    let mut unanswered: Vec<(String, Option<String>)> = Vec::new();
+    native
+        .iter()
+        .map(|msg| {
+            let mut out = message(msg);
+            if let Some(calls) = out.get_mut("tool_calls").and_then(|v| v.as_array_mut()) {
+                for call in calls {
+                    let id = format!("call_{next_id}");
+                    next_id += 1;
+                    let name = call["function"]["name"].as_str().map(str::to_string);
+                    call["id"] = json!(id);
+                    unanswered.push((id, name));
+                }
+            }
+            if msg["role"].as_str() == Some("tool") {
+                let name = msg.get("tool_name").and_then(|v| v.as_str());
+                let index = unanswered
+                    .iter()
+                    .position(|(_, n)| name.is_none() || n.as_deref() == name)
+                    .or((!unanswered.is_empty()).then_some(0));
+                let id = match index {
+                    Some(i) => unanswered.remove(i).0,
+                    None => {
+                        next_id += 1;
+                        format!("call_{}", next_id - 1)
+                    }
+                };
+                out["tool_call_id"] = json!(id);
+            }
+            out
+        })
+        .collect()
+}
+
+/// OpenAI chat message from a native one: images become `image_url` parts
+/// and tool-call arguments are serialized to strings. Ids are assigned by
+/// `translate_messages`.
+fn message(native: &Value) -> Value {
+    let role = native["role"].as_str().unwrap_or("user");
+    let text = native["content"].as_str().unwrap_or("");
+    let images = native
+        .get("images")
+        .and_then(|v| v.as_array())
+        .filter(|images| !images.is_empty());
+
+    let content = match images {
+        Some(images) => {
+            let mut parts = vec![json!({"type": "text", "text": text})];
+            for image in images.iter().filter_map(|v| v.as_str()) {
+                let url = format!("data:{};base64,{image}", image_mime(image));
+                parts.push(json!({"type": "image_url", "image_url": {"url": url}}));
+            }
+            json!(parts)
+        }
+        None => json!(text),
+    };
+    let mut out = json!({"role": role, "content": content});
+
+    if let Some(calls) = native.get("tool_calls").and_then(|v| v.as_array()) {
+        let calls: Vec<Value> = calls
+            .iter()
+            .map(|call| {
+                json!({
+                    "type": "function",
+                    "function": {
+                        "name": call["function"]["name"],
+                        "arguments": call["function"]["arguments"].to_string()
+                    }
+                })
+            })
+            .collect();
+        out["tool_calls"] = json!(calls);
+    }
+    out
+}
+
+/// Guess an image MIME type from the start of its base64 encoding.
+fn image_mime(base64: &str) -> &'static str {
+    if base64.starts_with("/9j/") {
+        "image/jpeg"
+    } else if base64.starts_with("R0lG") {
+        "image/gif"
+    } else if base64.starts_with("UklG") {
+        "image/webp"
+    } else {
+        "image/png"
+    }
+}
+
+/// Native Ollama response for `action` from an OpenAI-compatible reply.
+pub fn from_openai(action: &str, reply: &Value) -> Result<Value, OllamaError> {
+    let model = reply.get("model").cloned().unwrap_or(Value::Null);
+    let usage = |name: &str| reply.pointer(&format!("/usage/{name}")).cloned();
+
+    if action == "embeddings" {
+        let mut data = reply["data"].as_array().cloned().unwrap_or_default();
+        data.sort_by_key(|d| d["index"].as_u64().unwrap_or(0));
+        let embeddings: Vec<Value> = data.into_iter().map(|d| d["embedding"].clone()).collect();
+        return Ok(json!({
+            "model": model,
+            "embeddings": embeddings,
+            "prompt_eval_count": usage("prompt_tokens")
+        }));
+    }
+
+    let choice = reply
+        .pointer("/choices/0")
+        .ok_or_else(|| OllamaError::invalid_response("reply has no choices"))?;
+    let done_reason = match choice["finish_reason"].as_str() {
+        Some("length") => "length",
+        _ => "stop",
+    };
+    let mut out = Map::new();
+    out.insert("model".into(), model);
+    out.insert("done".into(), json!(true));
+    out.insert("done_reason".into(), json!(done_reason));
+    out.insert("prompt_eval_count".into(), json!(usage("prompt_tokens")));
+    out.insert("eval_count".into(), json!(usage("completion_tokens")));
+
+    let content = choice
+        .pointer("/message/content")
+        .or_else(|| choice.get("text"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    let thinking = choice.pointer("/message/reasoning_content").cloned();
+
+    if action == "generate" {
+        out.insert("response".into(), json!(content));
+        out.insert("thinking".into(), json!(thinking));
+    } else {
+        let calls: Vec<Value> = choice
+            .pointer("/message/tool_calls")
+            .and_then(|v| v.as_array())
+            .map(|calls| calls.iter().map(native_call).collect())
+            .unwrap_or_default();
+        let mut message = json!({"role": "assistant", "content": content});
+        if let Some(thinking) = thinking {
+            message["thinking"] = thinking;
+        }
+        if !calls.is_empty() {
+            message["tool_calls"] = json!(calls);
+        }
+        out.insert("message".into(), message);
+    }
+    Ok(Value::Object(out))
+}
+
+fn native_call(call: &Value) -> Value {
+    let arguments = match &call["function"]["arguments"] {
+        Value::String(raw) => serde_json::from_str(raw).unwrap_or_else(|_| json!(raw)),
+        args => args.clone(),
+    };
+    json!({"function": {"name": call["function"]["name"], "arguments": arguments}})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str) -> Value {
+        json!({"function": {"name": name, "arguments": {}}})
+    }
+
+    #[test]
+    fn tool_results_reference_unique_call_ids() {
+        let native = vec![
+            json!({"role": "user", "content": "weather in two cities"}),
+            json!({
+                "role": "assistant",
+                "content": "",
+                "tool_calls": [call("weather"), call("time")]
+            }),
+            json!({"role": "tool", "content": "12:00", "tool_name": "time"}),
+            json!({"role": "tool", "content": "sunny"}),
+            json!({"role": "assistant", "content": "", "tool_calls": [call("weather")]}),
+            json!({"role": "tool", "content": "rain", "tool_name": "weather"}),
+        ];
+        let out = translate_messages(&native);
+        let ids: Vec<&str> = [1, 4]
+            .iter()
+            .flat_map(|&i| out[i]["tool_calls"].as_array().unwrap())
+            .map(|c| c["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, vec!["call_0", "call_1", "call_2"]);
+        assert_eq!(out[2]["tool_call_id"], "call_1");
+        assert_eq!(out[3]["tool_call_id"], "call_0");
+        assert_eq!(out[5]["tool_call_id"], "call_2");
+        assert!(out[2].get("name").is_none());
+    }
+
+    #[test]
+    fn arguments_are_serialized() {
+        let native = vec![json!({
+            "role": "assistant",
+            "content": "",
+            "tool_calls": [{"function": {"name": "f", "arguments": {"x": 1}}}]
+        })];
+        let out = translate_messages(&native);
+        assert_eq!(
+            out[0]["tool_calls"][0]["function"]["arguments"],
+            "{\"x\":1}"
+        );
+    }
+}
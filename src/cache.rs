@@ -0,0 +1,207 @@
+//! Opt-in cache of raw Ollama responses for deterministic requests.
+//!
+//! Entries are keyed by a hash of the action and the exact request body sent
+//! upstream (model, inputs and options), stored one per variable with a
+//! shared index that drives TTL expiry and least-recently-used eviction.
+
+use serde_json::{json, Value};
+
+use crate::error::OllamaError;
+use crate::store;
+
+const INDEX: &str = "cache:index";
+pub const CACHEABLE: &[&str] = &["chat", "generate", "embeddings"];
+pub const DEFAULT_TTL_SECONDS: u64 = 3600;
+pub const DEFAULT_MAX_ENTRIES: u64 = 256;
+pub const DEFAULT_MAX_BYTES: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CacheSettings {
+    pub ttl: u64,
+    pub max_entries: u64,
+    pub max_bytes: u64,
+}
+
+impl CacheSettings {
+    /// Settings for `action`, or `None` when caching does not apply. A
+    /// request can opt out with `cache: false` even when the cache is on.
+    pub fn resolve(config: &Value, action: &str, requested: Option<bool>) -> Option<Self> {
+        let cache = config.get("cache")?;
+        let enabled = cache
+            .get("enabled")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        if !enabled || requested == Some(false) || !CACHEABLE.contains(&action) {
+            return None;
+        }
+        let int =
+            |name: &str, default: u64| cache.get(name).and_then(|v| v.as_u64()).unwrap_or(default);
+        Some(Self {
+            ttl: int("ttl_seconds", DEFAULT_TTL_SECONDS),
+            max_entries: int("max_entries", DEFAULT_MAX_ENTRIES).max(1),
+            max_bytes: int("max_bytes", DEFAULT_MAX_BYTES),
+        })
+    }
+}
+
+/// Stable key for a request. Object keys serialize in sorted order, so equal
+/// bodies hash equally regardless of how they were built.
+pub fn key(action: &str, body: &Value) -> String {
+    // 64-bit FNV-1a; collisions are not a concern at cache sizes.
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let text = format!("{action}\n{body}");
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{hash:016x}")
+}
+
+fn entry_key(key: &str) -> String {
+    format!("cache:{key}")
+}
+
+fn load_index() -> Value {
+    store::load(INDEX).unwrap_or_else(|| json!({"entries": {}, "hits": 0, "misses": 0}))
+}
+
+fn bump(index: &mut Value, counter: &str) {
+    index[counter] = json!(index[counter].as_u64().unwrap_or(0) + 1);
+}
+
+fn expired(meta: &Value, ttl: u64, now: u64) -> bool {
+    ttl > 0 && now >= meta["created_at"].as_u64().unwrap_or(0) + ttl
+}
+
+/// Look up a cached body, counting the hit or miss.
+pub fn get(settings: &CacheSettings, key: &str) -> Result<Option<Vec<u8>>, OllamaError> {
+    let mut index = load_index();
+    let now = store::now();
+    let fresh = index["entries"]
+        .get(key)
+        .is_some_and(|meta| !expired(meta, settings.ttl, now));
+    let body = if fresh {
+        store::load(&entry_key(key))
+            .and_then(|entry| entry["body"].as_str().map(|b| b.as_bytes().to_vec()))
+    } else {
+        None
+    };
+
+    match &body {
+        Some(_) => {
+            index["entries"][key]["used_at"] = json!(now);
+            bump(&mut index, "hits");
+        }
+        None => {
+            if let Some(entries) = index["entries"].as_object_mut() {
+                if entries.remove(key).is_some() {
+                    store::remove(&entry_key(key))?;
+                }
+            }
+            bump(&mut index, "misses");
+        }
+    }
+    store::save(INDEX, &index)?;
+    Ok(body)
+}
+
+/// Store a response body, then evict expired and least recently used
+/// entries until the cache fits its limits.
+pub fn put(settings: &CacheSettings, key: &str, body: &[u8]) -> Result<(), OllamaError> {
+    let body = String::from_utf8_lossy(body);
+    if body.len() as u64 > settings.max_bytes {
+        return Ok(());
+    }
+    let now = store::now();
+    store::save(&entry_key(key), &json!({"body": body}))?;
+
+    let mut index = load_index();
+    index["entries"][key] = json!({"created_at": now, "used_at": now, "bytes": body.len()});
+
+    let mut entries: Vec<(String, Value)> = index["entries"]
+        .as_object()
+        .map(|m| m.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+        .unwrap_or_default();
+    entries.sort_by_key(|(_, meta)| meta["used_at"].as_u64().unwrap_or(0));
+
+    let mut total: u64 = entries
+        .iter()
+        .map(|(_, m)| m["bytes"].as_u64().unwrap_or(0))
+        .sum();
+    let mut count = entries.len() as u64;
+    let mut evicted = Vec::new();
+    for (k, meta) in &entries {
+        let over = count > settings.max_entries || total > settings.max_bytes;
+        if k != key && (over || expired(meta, settings.ttl, now)) {
+            total -= meta["bytes"].as_u64().unwrap_or(0);
+            count -= 1;
+            evicted.push(k.clone());
+        }
+    }
+    if let Some(map) = index["entries"].as_object_mut() {
+        for k in &evicted {
+            map.remove(k);
+            store::remove(&entry_key(k))?;
+        }
+    }
+    index["evictions"] = json!(index["evictions"].as_u64().unwrap_or(0) + evicted.len() as u64);
+    store::save(INDEX, &index)
+}
+
+/// Counters and occupancy for the `cache_stats` action.
+pub fn stats(config: &Value) -> Value {
+    let index = load_index();
+    let entries = index["entries"].as_object();
+    let bytes: u64 = entries
+        .map(|m| m.values().map(|e| e["bytes"].as_u64().unwrap_or(0)).sum())
+        .unwrap_or(0);
+    let hits = index["hits"].as_u64().unwrap_or(0);
+    let misses = index["misses"].as_u64().unwrap_or(0);
+    let lookups = hits + misses;
+    json!({
+        "enabled": config.pointer("/cache/enabled").and_then(|v| v.as_bool()).unwrap_or(false),
+        "entries": entries.map_or(0, |m| m.len()),
+        "bytes": bytes,
+        "hits": hits,
+        "misses": misses,
+        "evictions": index["evictions"].as_u64().unwrap_or(0),
+        "hit_rate": if lookups > 0 { hits as f64 / lookups as f64 } else { 0.0 }
+    })
+}
+
+/// Drop every entry and reset the counters, returning how many went.
+pub fn clear() -> Result<usize, OllamaError> {
+    let index = load_index();
+    let keys: Vec<String> = index["entries"]
+        .as_object()
+        .map(|m| m.keys().cloned().collect())
+        .unwrap_or_default();
+    for k in &keys {
+        store::remove(&entry_key(k))?;
+    }
+    store::remove(INDEX)?;
+    Ok(keys.len())
+}
+
+/// JSON schema for the `cache` object in `config_schema()`.
+pub fn schema() -> Value {
+    json!({
+        "type": "object",
+        "description": "Opt-in response cache for chat, generate and embeddings; \
+                        best suited to deterministic requests (fixed seed, temperature 0)",
+        "properties": {
+            "enabled": {"type": "boolean", "default": false},
+            "ttl_seconds": {
+                "type": "integer",
+                "description": "Lifetime of an entry; 0 keeps entries until evicted",
+                "default": DEFAULT_TTL_SECONDS
+            },
+            "max_entries": {"type": "integer", "default": DEFAULT_MAX_ENTRIES},
+            "max_bytes": {
+                "type": "integer",
+                "description": "Upper bound on the total size of cached responses",
+                "default": DEFAULT_MAX_BYTES
+            }
+        }
+    })
+}
@@ -0,0 +1,190 @@
+//! Prompting and fenced-block extraction for the `code` action.
+
+use serde_json::{json, Value};
+
+/// System prompt for code generation, specialized to `language` if known.
+pub fn system_prompt(language: Option<&str>) -> String {
+    let mut prompt = String::from(
+        "You are an expert software engineer. Write correct, idiomatic, \
+         production-quality code. Put every piece of code in a fenced block \
+         tagged with its language. Keep explanations short and outside the \
+         code blocks. Do not invent APIs; say so when something is uncertain.",
+    );
+    if let Some(language) = language {
+        prompt.push_str(&format!(" Unless told otherwise, write {language}."));
+    }
+    prompt
+}
+
+/// Build the user turn from the task, existing code and constraints.
+pub fn user_prompt(
+    task: &str,
+    language: Option<&str>,
+    code: Option<&str>,
+    constraints: &[String],
+) -> String {
+    let mut prompt = format!("Task: {task}\n");
+    if let Some(code) = code {
+        let fence = language.unwrap_or("");
+        prompt.push_str(&format!("\nExisting code:\n```{fence}\n{code}\n```\n"));
+    }
+    if !constraints.is_empty() {
+        prompt.push_str("\nConstraints:\n");
+        for constraint in constraints {
+            prompt.push_str(&format!("- {constraint}\n"));
+        }
+    }
+    prompt
+}
+
+/// Split model output into fenced code blocks and the surrounding prose.
+/// Blocks without an info string take `default_language`.
+pub fn extract_blocks(text: &str, default_language: Option<&str>) -> (Vec<Value>, String) {
+    let mut blocks = Vec::new();
+    let mut prose = Vec::new();
+    let mut open: Option<(String, String, Vec<&str>)> = None;
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        match &mut open {
+            Some((fence, language, body)) => {
+                if trimmed.starts_with(fence.as_str()) && trimmed.trim_end() == fence.as_str() {
+                    blocks.push(json!({"language": language, "content": body.join("\n")}));
+                    open = None;
+                } else {
+                    body.push(line);
+                }
+            }
+            None => match fence_of(trimmed) {
+                Some(fence) => {
+                    let info = trimmed[fence.len()..].trim();
+                    let language = info
+                        .split_whitespace()
+                        .next()
+                        .or(default_language)
+                        .unwrap_or("")
+                        .to_string();
+                    open = Some((fence, language, Vec::new()));
+                }
+                None => prose.push(line),
+            },
+        }
+    }
+
+    // An unterminated fence still yields its content.
+    if let Some((_, language, body)) = open {
+        blocks.push(json!({"language": language, "content": body.join("\n")}));
+    }
+
+    (blocks, prose.join("\n").trim().to_string())
+}
+
+/// The fence a line opens with (three or more backticks or tildes), if any.
+fn fence_of(line: &str) -> Option<String> {
+    let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.chars().take_while(|c| *c == marker).count();
+    (len >= 3).then(|| marker.to_string().repeat(len))
+}
+
+/// Clean up a fill-in-the-middle insertion for inline display.
+///
+/// Drops stray code fences, cuts the text where it starts repeating the
+/// suffix, keeps mid-line completions to a single line, and avoids doubling
+/// whitespace the suffix already provides.
+pub fn trim_completion(raw: &str, suffix: &str) -> String {
+    let mut text = raw.trim_start_matches('\n');
+    if let Some(fence) = fence_of(text.trim_start()) {
+        let after = text.trim_start()[fence.len()..].split_once('\n');
+        text = after.map(|(_, rest)| rest).unwrap_or("");
+        if let Some(end) = text.rfind(&fence) {
+            text = &text[..end];
+        }
+    }
+
+    let mut out = text.to_string();
+
+    // Models often run on into the code that follows the cursor.
+    let anchor = suffix
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("");
+    if anchor.len() >= 3 {
+        if let Some(pos) = out.find(anchor) {
+            out.truncate(pos);
+        }
+    }
+
+    // Mid-line: the rest of the current line already follows the cursor.
+    let rest_of_line = suffix.split('\n').next().unwrap_or("");
+    if !rest_of_line.trim().is_empty() {
+        if let Some(pos) = out.find('\n') {
+            out.truncate(pos);
+        }
+    }
+
+    if suffix.starts_with(char::is_whitespace) || suffix.is_empty() {
+        out.truncate(out.trim_end().len());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_blocks_from_prose() {
+        let text = "Here you go:\n\n```rust\nfn main() {}\n```\n\nThen:\n~~~\nls -l\n~~~\nDone.";
+        let (blocks, explanation) = extract_blocks(text, Some("sh"));
+        assert_eq!(
+            blocks,
+            vec![
+                json!({"language": "rust", "content": "fn main() {}"}),
+                json!({"language": "sh", "content": "ls -l"}),
+            ]
+        );
+        assert_eq!(explanation, "Here you go:\n\n\nThen:\nDone.");
+    }
+
+    #[test]
+    fn longer_fences_can_contain_shorter_ones() {
+        let text = "````markdown\n```py\nprint(1)\n```\n````";
+        let (blocks, explanation) = extract_blocks(text, None);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0]["language"], "markdown");
+        assert_eq!(blocks[0]["content"], "```py\nprint(1)\n```");
+        assert!(explanation.is_empty());
+    }
+
+    #[test]
+    fn unterminated_fence_still_yields_its_content() {
+        let (blocks, _) = extract_blocks("```\nlet x = 1;\nlet y = 2;", None);
+        assert_eq!(
+            blocks,
+            vec![json!({"language": "", "content": "let x = 1;\nlet y = 2;"})]
+        );
+    }
+
+    #[test]
+    fn strips_fences_around_a_completion() {
+        assert_eq!(
+            trim_completion("\n```rust\nlet x = 1;\n```\n", ""),
+            "let x = 1;"
+        );
+    }
+
+    #[test]
+    fn cuts_where_the_suffix_repeats() {
+        let raw = "let total = a + b;\nreturn total;\n}";
+        let suffix = "\n    return total;\n}\n";
+        assert_eq!(trim_completion(raw, suffix), "let total = a + b;");
+    }
+
+    #[test]
+    fn mid_line_completions_stay_on_one_line() {
+        assert_eq!(trim_completion("a, b\nfoo(c", ");\n"), "a, b");
+        // Trailing whitespace is kept when the suffix does not supply any.
+        assert_eq!(trim_completion("a, ", "b);"), "a, ");
+    }
+}
@@ -0,0 +1,334 @@
+//! Context-window management for the chat path.
+//!
+//! Ollama truncates over-long prompts from the front, which silently drops
+//! the system prompt. Instead, the history is fitted to `num_ctx` here:
+//! leading system messages and the newest turn are always kept, and older
+//! turns are dropped (or handed back for summarization) until it fits.
+
+use serde_json::{json, Value};
+
+/// Assumed context window when a strategy is requested explicitly but
+/// `num_ctx` is not set (Ollama's own default).
+const DEFAULT_NUM_CTX: u64 = 4096;
+const DEFAULT_RESERVE_TOKENS: u64 = 512;
+const DEFAULT_WINDOW: u64 = 20;
+/// Rough per-message cost of the chat template's role markers.
+const MESSAGE_OVERHEAD: u64 = 4;
+/// Marks the system message that carries a compacted summary.
+const SUMMARY_PREFIX: &str = "Summary of the earlier conversation:\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    None,
+    DropOldest,
+    SlidingWindow,
+    Summarize,
+}
+
+impl Strategy {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Strategy::None),
+            "drop_oldest" => Some(Strategy::DropOldest),
+            "sliding_window" => Some(Strategy::SlidingWindow),
+            "summarize" => Some(Strategy::Summarize),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Strategy::None => "none",
+            Strategy::DropOldest => "drop_oldest",
+            Strategy::SlidingWindow => "sliding_window",
+            Strategy::Summarize => "summarize",
+        }
+    }
+}
+
+pub struct ContextSettings {
+    pub strategy: Strategy,
+    pub num_ctx: u64,
+    pub reserve_tokens: u64,
+    pub window: usize,
+    pub summary_model: Option<String>,
+}
+
+impl ContextSettings {
+    /// Resolve settings from the request's `context` object over the
+    /// config's, taking `num_ctx` from the merged model options.
+    pub fn resolve(
+        config: &Value,
+        request: Option<&Value>,
+        options: Option<&Value>,
+    ) -> Result<Self, String> {
+        if request.is_some_and(|r| !r.is_object()) {
+            return Err("context must be an object".to_string());
+        }
+        let lookup = |key: &str| {
+            request
+                .and_then(|r| r.get(key))
+                .or_else(|| config.get("context").and_then(|c| c.get(key)))
+        };
+
+        let num_ctx = options
+            .and_then(|o| o.get("num_ctx"))
+            .and_then(|v| v.as_u64());
+        let strategy = match lookup("strategy") {
+            Some(v) => v
+                .as_str()
+                .and_then(Strategy::parse)
+                .ok_or_else(|| format!("unknown context strategy: {v}"))?,
+            // The model's real window (Modelfile, OLLAMA_CONTEXT_LENGTH) is
+            // unknown here, so only trim by default when num_ctx is given.
+            None if num_ctx.is_some() => Strategy::DropOldest,
+            None => Strategy::None,
+        };
+        let num_ctx = num_ctx.unwrap_or(DEFAULT_NUM_CTX);
+
+        Ok(Self {
+            strategy,
+            num_ctx,
+            reserve_tokens: lookup("reserve_tokens")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(DEFAULT_RESERVE_TOKENS),
+            window: lookup("window")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(DEFAULT_WINDOW)
+                .max(1) as usize,
+            summary_model: lookup("summary_model")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+        })
+    }
+
+    /// Tokens available for the prompt after reserving room for the reply.
+    pub fn budget(&self) -> u64 {
+        self.num_ctx.saturating_sub(self.reserve_tokens).max(1)
+    }
+}
+
+/// Estimate a message's token count at roughly four characters per token.
+pub fn estimate_tokens(message: &Value) -> u64 {
+    let content = message
+        .get("content")
+        .and_then(|v| v.as_str())
+        .map(|s| s.chars().count() as u64)
+        .unwrap_or(0);
+    let calls = message
+        .get("tool_calls")
+        .map(|v| v.to_string().len() as u64)
+        .unwrap_or(0);
+    (content + calls).div_ceil(4) + MESSAGE_OVERHEAD
+}
+
+pub struct Fitted {
+    pub messages: Vec<Value>,
+    /// Older turns removed to fit, in their original order.
+    pub dropped: Vec<Value>,
+}
+
+/// Trim `messages` to the budget according to the strategy.
+pub fn fit(messages: Vec<Value>, settings: &ContextSettings) -> Fitted {
+    let split = messages
+        .iter()
+        .position(|m| m.get("role").and_then(|v| v.as_str()) != Some("system"))
+        .unwrap_or(messages.len());
+    let mut head = messages;
+    let mut body = head.split_off(split);
+    let mut dropped = Vec::new();
+
+    if settings.strategy == Strategy::SlidingWindow && body.len() > settings.window {
+        dropped.extend(body.drain(..body.len() - settings.window));
+    }
+
+    if settings.strategy != Strategy::None {
+        let head_tokens: u64 = head.iter().map(estimate_tokens).sum();
+        let mut body_tokens: u64 = body.iter().map(estimate_tokens).sum();
+        let budget = settings.budget();
+        while head_tokens + body_tokens > budget && body.len() > 1 {
+            let oldest = body.remove(0);
+            body_tokens -= estimate_tokens(&oldest);
+            dropped.push(oldest);
+        }
+    }
+
+    // A tool result is meaningless without the assistant call before it.
+    while body.len() > 1 && body[0].get("role").and_then(|v| v.as_str()) == Some("tool") {
+        dropped.push(body.remove(0));
+    }
+
+    head.extend(body);
+    Fitted {
+        messages: head,
+        dropped,
+    }
+}
+
+pub fn summary_message(summary: &str) -> Value {
+    json!({"role": "system", "content": format!("{SUMMARY_PREFIX}{summary}")})
+}
+
+pub fn is_summary(message: &Value) -> bool {
+    message.get("role").and_then(|v| v.as_str()) == Some("system")
+        && message
+            .get("content")
+            .and_then(|v| v.as_str())
+            .is_some_and(|c| c.starts_with(SUMMARY_PREFIX))
+}
+
+/// Messages asking a model to compact `dropped` turns into a summary,
+/// folding in any summary produced earlier.
+pub fn summary_request(previous: Option<&str>, dropped: &[Value]) -> Value {
+    let mut transcript = String::new();
+    if let Some(previous) = previous {
+        transcript.push_str("Earlier summary:\n");
+        transcript.push_str(previous);
+        transcript.push_str("\n\n");
+    }
+    for message in dropped {
+        let role = message
+            .get("role")
+            .and_then(|v| v.as_str())
+            .unwrap_or("user");
+        let content = message
+            .get("content")
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+        transcript.push_str(&format!("{role}: {content}\n"));
+    }
+    json!([
+        {
+            "role": "system",
+            "content": "Summarize the conversation below in a few sentences. Keep facts, \
+                        decisions, names and open questions; omit pleasantries."
+        },
+        {"role": "user", "content": transcript}
+    ])
+}
+
+/// JSON schema for the `context` object in `config_schema()`.
+pub fn schema() -> Value {
+    json!({
+        "type": "object",
+        "description": "How chat history is fitted to num_ctx; overridable per request",
+        "properties": {
+            "strategy": {
+                "type": "string",
+                "enum": ["drop_oldest", "summarize", "sliding_window", "none"],
+                "description": "Defaults to drop_oldest when options.num_ctx is set, \
+                                otherwise none"
+            },
+            "reserve_tokens": {
+                "type": "integer",
+                "description": "Tokens kept free for the reply",
+                "default": DEFAULT_RESERVE_TOKENS
+            },
+            "window": {
+                "type": "integer",
+                "description": "Turns kept by sliding_window",
+                "default": DEFAULT_WINDOW
+            },
+            "summary_model": {
+                "type": "string",
+                "description": "Model used by summarize; defaults to the chat model"
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(strategy: Strategy, num_ctx: u64) -> ContextSettings {
+        ContextSettings {
+            strategy,
+            num_ctx,
+            reserve_tokens: 10,
+            window: 2,
+            summary_model: None,
+        }
+    }
+
+    fn turn(role: &str, n: usize) -> Value {
+        // 40 characters: 10 tokens plus the per-message overhead.
+        json!({"role": role, "content": format!("{n:0>40}")})
+    }
+
+    fn contents(messages: &[Value]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|m| m["content"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn drop_oldest_keeps_system_and_newest_turns() {
+        let messages = vec![
+            json!({"role": "system", "content": "sys"}),
+            turn("user", 1),
+            turn("assistant", 2),
+            turn("user", 3),
+            turn("assistant", 4),
+        ];
+        // Budget 50: system costs 5 and each turn 14, so one turn must go.
+        let fitted = fit(messages, &settings(Strategy::DropOldest, 60));
+        assert_eq!(fitted.messages.len(), 4);
+        assert_eq!(fitted.messages[0]["role"], "system");
+        assert_eq!(contents(&fitted.dropped), vec![format!("{:0>40}", 1)]);
+    }
+
+    #[test]
+    fn always_keeps_the_newest_turn() {
+        let messages = vec![turn("user", 1), turn("user", 2)];
+        let fitted = fit(messages, &settings(Strategy::DropOldest, 1));
+        assert_eq!(contents(&fitted.messages), vec![format!("{:0>40}", 2)]);
+        assert_eq!(fitted.dropped.len(), 1);
+    }
+
+    #[test]
+    fn none_leaves_history_untouched() {
+        let messages: Vec<Value> = (0..10).map(|n| turn("user", n)).collect();
+        let fitted = fit(messages.clone(), &settings(Strategy::None, 1));
+        assert_eq!(fitted.messages, messages);
+        assert!(fitted.dropped.is_empty());
+    }
+
+    #[test]
+    fn sliding_window_drops_orphaned_tool_results() {
+        let messages = vec![
+            turn("user", 1),
+            turn("assistant", 2),
+            turn("tool", 3),
+            turn("assistant", 4),
+        ];
+        let fitted = fit(messages, &settings(Strategy::SlidingWindow, 4096));
+        assert_eq!(contents(&fitted.messages), vec![format!("{:0>40}", 4)]);
+        assert_eq!(fitted.dropped.len(), 3);
+    }
+
+    #[test]
+    fn only_trims_by_default_when_num_ctx_is_known() {
+        let config = json!({});
+        let unknown = ContextSettings::resolve(&config, None, None).unwrap();
+        assert_eq!(unknown.strategy, Strategy::None);
+
+        let options = json!({"num_ctx": 8192});
+        let known = ContextSettings::resolve(&config, None, Some(&options)).unwrap();
+        assert_eq!(known.strategy, Strategy::DropOldest);
+        assert_eq!(known.budget(), 8192 - DEFAULT_RESERVE_TOKENS);
+    }
+
+    #[test]
+    fn request_overrides_config() {
+        let config = json!({"context": {"strategy": "summarize", "window": 5}});
+        let request = json!({"strategy": "sliding_window"});
+        let resolved = ContextSettings::resolve(&config, Some(&request), None).unwrap();
+        assert_eq!(resolved.strategy, Strategy::SlidingWindow);
+        assert_eq!(resolved.window, 5);
+
+        let bad = json!({"strategy": "truncate"});
+        assert!(ContextSettings::resolve(&config, Some(&bad), None).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//! Bounded dead-letter list for agent messages that could not be processed.
+
+use serde_json::{json, Value};
+
+use crate::error::OllamaError;
+use crate::store;
+
+const KEY: &str = "dead_letters";
+pub const DEFAULT_LIMIT: u64 = 100;
+
+fn load() -> Value {
+    store::load(KEY).unwrap_or_else(|| json!({"next_id": 1, "entries": []}))
+}
+
+fn entries_mut(state: &mut Value) -> &mut Vec<Value> {
+    if !state["entries"].is_array() {
+        state["entries"] = json!([]);
+    }
+    state["entries"]
+        .as_array_mut()
+        .expect("entries is an array")
+}
+
+/// Record a failed message, evicting the oldest entries beyond `limit`.
+pub fn push(
+    from: &str,
+    payload: &Value,
+    error: &OllamaError,
+    limit: u64,
+) -> Result<(), OllamaError> {
+    let mut state = load();
+    let id = state["next_id"].as_u64().unwrap_or(1);
+    state["next_id"] = json!(id + 1);
+
+    let entries = entries_mut(&mut state);
+    entries.push(json!({
+        "id": id,
+        "from": from,
+        "payload": payload,
+        "error": error.to_json(),
+        "failed_at": store::now(),
+        "attempts": 1
+    }));
+    let limit = limit.max(1) as usize;
+    if entries.len() > limit {
+        let excess = entries.len() - limit;
+        entries.drain(..excess);
+    }
+    store::save(KEY, &state)
+}
+
+/// Entries matching `ids`, or all entries when `ids` is `None`.
+pub fn list(ids: Option<&[u64]>) -> Vec<Value> {
+    let state = load();
+    state["entries"]
+        .as_array()
+        .map(|entries| {
+            entries
+                .iter()
+                .filter(|e| matches(e, ids))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Drop entries matching `ids` (all when `None`), returning how many went.
+pub fn remove(ids: Option<&[u64]>) -> Result<usize, OllamaError> {
+    let mut state = load();
+    let entries = entries_mut(&mut state);
+    let before = entries.len();
+    entries.retain(|e| !matches(e, ids));
+    let removed = before - entries.len();
+    store::save(KEY, &state)?;
+    Ok(removed)
+}
+
+/// Note another failed retry of entry `id`.
+pub fn record_failure(id: u64, error: &OllamaError) -> Result<(), OllamaError> {
+    let mut state = load();
+    if let Some(entry) = entries_mut(&mut state)
+        .iter_mut()
+        .find(|e| e["id"].as_u64() == Some(id))
+    {
+        entry["error"] = error.to_json();
+        entry["failed_at"] = json!(store::now());
+        entry["attempts"] = json!(entry["attempts"].as_u64().unwrap_or(1) + 1);
+    }
+    store::save(KEY, &state)
+}
+
+fn matches(entry: &Value, ids: Option<&[u64]>) -> bool {
+    match ids {
+        Some(ids) => entry["id"].as_u64().is_some_and(|id| ids.contains(&id)),
+        None => true,
+    }
+}
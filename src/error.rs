@@ -0,0 +1,160 @@
+//! Structured errors returned to callers in a `{"error": {...}}` envelope.
+
+use serde_json::{json, Value};
+
+/// Broad failure class, so callers can branch without parsing messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    ModelNotFound,
+    BadRequest,
+    ServerError,
+    Unreachable,
+    InvalidResponse,
+    RateLimited,
+    Forbidden,
+}
+
+impl Category {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Category::ModelNotFound => "model_not_found",
+            Category::BadRequest => "bad_request",
+            Category::ServerError => "server_error",
+            Category::Unreachable => "unreachable",
+            Category::InvalidResponse => "invalid_response",
+            Category::RateLimited => "rate_limited",
+            Category::Forbidden => "forbidden",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "model_not_found" => Some(Category::ModelNotFound),
+            "bad_request" => Some(Category::BadRequest),
+            "server_error" => Some(Category::ServerError),
+            "unreachable" => Some(Category::Unreachable),
+            "invalid_response" => Some(Category::InvalidResponse),
+            "rate_limited" => Some(Category::RateLimited),
+            "forbidden" => Some(Category::Forbidden),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OllamaError {
+    pub category: Category,
+    pub message: String,
+    /// Upstream HTTP status, when Ollama answered at all.
+    pub status: Option<u16>,
+    pub details: Option<Value>,
+    /// How many requests were made before giving up.
+    pub attempts: Option<u32>,
+}
+
+impl OllamaError {
+    pub fn new(category: Category, message: impl Into<String>) -> Self {
+        Self {
+            category,
+            message: message.into(),
+            status: None,
+            details: None,
+            attempts: None,
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(Category::BadRequest, message)
+    }
+
+    pub fn unreachable(message: impl Into<String>) -> Self {
+        Self::new(Category::Unreachable, message)
+    }
+
+    pub fn invalid_response(message: impl Into<String>) -> Self {
+        Self::new(Category::InvalidResponse, message)
+    }
+
+    /// Classify a non-2xx response, preferring Ollama's `{"error": "..."}` body
+    /// or the OpenAI-style `{"error": {"message": "..."}}`.
+    pub fn from_status(status: u16, body: &[u8]) -> Self {
+        let message = serde_json::from_slice::<Value>(body)
+            .ok()
+            .and_then(|v| {
+                let error = v.get("error")?;
+                error
+                    .as_str()
+                    .or_else(|| error.get("message").and_then(|m| m.as_str()))
+                    .map(str::to_string)
+            })
+            .unwrap_or_else(|| String::from_utf8_lossy(body).trim().to_string());
+        let category = match status {
+            404 => Category::ModelNotFound,
+            _ if is_model_missing(&message) => Category::ModelNotFound,
+            400..=499 => Category::BadRequest,
+            500..=599 => Category::ServerError,
+            _ => Category::InvalidResponse,
+        };
+        let message = if message.is_empty() {
+            format!("ollama returned status {status}")
+        } else {
+            message
+        };
+        Self {
+            status: Some(status),
+            ..Self::new(category, message)
+        }
+    }
+
+    /// Classify an `error` line embedded in a streamed response.
+    pub fn from_stream(message: &str) -> Self {
+        let category = if is_model_missing(message) {
+            Category::ModelNotFound
+        } else {
+            Category::ServerError
+        };
+        Self::new(category, message)
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// The inner error object, without the envelope.
+    pub fn to_json(&self) -> Value {
+        let mut err = json!({
+            "category": self.category.as_str(),
+            "message": self.message,
+            "status": self.status
+        });
+        if let Some(details) = &self.details {
+            err["details"] = details.clone();
+        }
+        if let Some(attempts) = self.attempts {
+            err["attempts"] = json!(attempts);
+        }
+        err
+    }
+
+    pub fn envelope(&self) -> Value {
+        json!({"error": self.to_json()})
+    }
+}
+
+impl From<String> for OllamaError {
+    fn from(message: String) -> Self {
+        Self::bad_request(message)
+    }
+}
+
+impl From<serde_json::Error> for OllamaError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::invalid_response(format!("malformed JSON from ollama: {e}"))
+    }
+}
+
+fn is_model_missing(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    message.contains("model") && message.contains("not found")
+}
@@ -1,48 +1,161 @@
+use std::time::Instant;
+
 use extism_pdk::*;
 use magi_pdk::DataType;
 use serde_json::json;
 
+use crate::backend::Backend;
+use crate::cache::CacheSettings;
+use crate::context::{ContextSettings, Strategy};
+use crate::error::{Category, OllamaError};
+use crate::models::Capability;
+use crate::protocol::AgentRequest;
+use crate::retry::RetryPolicy;
+use crate::router::Endpoint;
+use crate::session::Session;
+
+mod auth;
+mod backend;
+mod cache;
+mod code;
+mod context;
+mod dead_letter;
+mod error;
+mod metrics;
+mod modelfile;
+mod models;
+mod openai;
+mod options;
+mod protocol;
+mod rate_limit;
+mod reasoning;
+mod retry;
+mod router;
+mod schema;
+mod session;
+mod store;
+
+const DEFAULT_MAX_IMAGE_BYTES: u64 = 20 * 1024 * 1024;
+const DEFAULT_EMBED_BATCH_SIZE: u64 = 64;
+const CODE_TEMPERATURE: f64 = 0.2;
+
 // =============================================================================
 // Plugin exports
 // =============================================================================
 
 #[plugin_fn]
 pub fn describe() -> FnResult<Json<DataType>> {
+    let capabilities: Vec<_> = capabilities()
+        .iter()
+        .map(|(name, description)| json!({"name": name, "description": description}))
+        .collect();
     Ok(Json(DataType::from_json(json!({
         "name": "ollama",
         "version": "0.1.0",
         "description": "ACP agent for local LLM inference via Ollama",
         "label": "acp",
-        "capabilities": [
-            {"name": "chat-completion", "description": "Generate chat responses via local Ollama models"},
-            {"name": "code-generation", "description": "Generate code with local models"},
-            {"name": "embeddings", "description": "Generate text embeddings"}
-        ]
+        "capabilities": capabilities
     }))))
 }
 
+/// Capabilities advertised by `describe()` and `start()`.
+fn capabilities() -> Vec<(&'static str, &'static str)> {
+    let mut caps = vec![
+        ("chat-completion", "Generate chat responses via local Ollama models"),
+        ("code-generation", "Generate code with local models"),
+        ("embeddings", "Generate text embeddings"),
+    ];
+    let config = magi_pdk::get_config().unwrap_or_default();
+    if config.get("vision").and_then(|v| v.as_bool()).unwrap_or(false) {
+        caps.push(("vision", "Describe and reason about images with multimodal models"));
+    }
+    caps
+}
+
 #[plugin_fn]
 pub fn config_schema() -> FnResult<Json<serde_json::Value>> {
+    let (retry_schema, timeouts_schema) = retry::schema();
+    let (endpoints_schema, routing_schema) = router::schema();
     Ok(Json(json!({
         "type": "object",
         "properties": {
             "ollama_url": {
                 "type": "string",
-                "description": "Ollama API base URL",
+                "description": "Base URL of the Ollama or OpenAI-compatible server",
                 "default": "http://localhost:11434"
             },
+            "endpoints": endpoints_schema,
+            "routing": routing_schema,
+            "backend": {
+                "type": "string",
+                "enum": ["ollama-native", "openai-compatible"],
+                "description": "API used for chat, generate and embeddings: Ollama's /api \
+                                endpoints or the OpenAI-style /v1 endpoints",
+                "default": "ollama-native"
+            },
+            "api_key": {
+                "type": "string",
+                "description": "Bearer token used when the applicable auth sets no \
+                                credentials of its own",
+                "format": "password",
+                "writeOnly": true
+            },
+            "auth": auth::schema(),
             "model": {
                 "type": "string",
                 "description": "Default model to use",
                 "default": "llama3.2"
-            }
+            },
+            "code_model": {
+                "type": "string",
+                "description": "Model used by the code action when no code alias is set; \
+                                defaults to model"
+            },
+            "model_aliases": models::schema(),
+            "vision": {
+                "type": "boolean",
+                "description": "Advertise the vision capability for multimodal models",
+                "default": false
+            },
+            "max_image_bytes": {
+                "type": "integer",
+                "description": "Maximum decoded size of a single attached image",
+                "default": DEFAULT_MAX_IMAGE_BYTES
+            },
+            "embed_batch_size": {
+                "type": "integer",
+                "description": "Maximum texts per /api/embed request; larger batches are split",
+                "default": DEFAULT_EMBED_BATCH_SIZE
+            },
+            "agent_actions": {
+                "type": "array",
+                "items": {"type": "string"},
+                "description": "Actions peer agents may request through the message protocol",
+                "default": protocol::default_actions()
+            },
+            "rate_limits": rate_limit::schema(),
+            "cache": cache::schema(),
+            "dead_letter_limit": {
+                "type": "integer",
+                "description": "Failed agent messages kept for inspection and retry",
+                "default": dead_letter::DEFAULT_LIMIT
+            },
+            "drop_thinking": {
+                "type": "boolean",
+                "description": "Strip model reasoning from replies sent to other agents",
+                "default": false
+            },
+            "options": options::schema(),
+            "context": context::schema(),
+            "retry": retry_schema,
+            "timeouts": timeouts_schema
         }
     })))
 }
 
 #[plugin_fn]
 pub fn init(Json(_input): Json<DataType>) -> FnResult<Json<DataType>> {
-    magi_pdk::log_info("Ollama plugin initialized");
+    log("Ollama plugin initialized");
     Ok(Json(DataType::from_json(json!({"success": true}))))
 }
 
@@ -51,19 +164,15 @@ pub fn start() -> FnResult<Json<DataType>> {
     let _ = magi_pdk::agent_register(
         "ollama",
         "Local LLM inference agent via Ollama",
-        &[
-            ("chat-completion", "Generate chat responses via local Ollama models"),
-            ("code-generation", "Generate code with local models"),
-            ("embeddings", "Generate text embeddings"),
-        ],
+        &capabilities(),
     );
-    magi_pdk::log_info("Ollama ACP agent registered");
+    log("Ollama ACP agent registered");
     Ok(Json(DataType::from_json(json!({"status": "running"}))))
 }
 
 #[plugin_fn]
 pub fn stop() -> FnResult<Json<DataType>> {
-    magi_pdk::log_info("Ollama ACP agent stopped");
+    log("Ollama ACP agent stopped");
     Ok(Json(DataType::from_json(json!({"status": "stopped"}))))
 }
 
@@ -85,15 +194,67 @@ pub fn process(Json(input): Json<DataType>) -> FnResult<Json<DataType>> {
         .and_then(|v| v.as_str())
         .unwrap_or("llama3.2");
 
-    match action.as_str() {
-        "chat" => chat(base_url, model, &input),
-        "generate" => generate(base_url, model, &input),
-        "embeddings" => embeddings(base_url, model, &input),
-        "list_models" => list_models(base_url),
+    let result = match action.as_str() {
         "poll" => poll_messages(base_url, model),
-        _ => Ok(Json(DataType::from_json(
-            json!({"error": format!("unknown action: {action}")}),
-        ))),
+        "dead_letters_retry" => dead_letters_retry(base_url, model, &input),
+        _ => dispatch_metered(&action, base_url, model, &input, "local"),
+    };
+
+    Ok(Json(DataType::from_json(
+        result.unwrap_or_else(|e| e.envelope()),
+    )))
+}
+
+/// `dispatch`, timing inference actions and folding their outcome into the
+/// metrics under `sender`.
+fn dispatch_metered(
+    action: &str,
+    base_url: &str,
+    model: &str,
+    input: &DataType,
+    sender: &str,
+) -> ActionResult {
+    let started = Instant::now();
+    let result = dispatch(action, base_url, model, input);
+    if metrics::tracks(action) {
+        let requested = input.get("model").and_then(|v| v.as_str()).unwrap_or(model);
+        let served = result.as_ref().ok().and_then(|r| r["model"].as_str());
+        let served = served.unwrap_or(requested);
+        if let Err(e) = metrics::record(sender, served, result.as_ref(), started.elapsed()) {
+            log(&format!("metrics not recorded: {}", e.message));
+        }
+    }
+    result
+}
+
+/// Route an action to its handler. Shared by `process` and agent requests.
+fn dispatch(action: &str, base_url: &str, model: &str, input: &DataType) -> ActionResult {
+    match action {
+        "chat" => chat(base_url, model, input),
+        "generate" => generate(base_url, model, input),
+        "embeddings" => embeddings(base_url, model, input),
+        "code" => code(base_url, model, input),
+        "complete" => complete(base_url, model, input),
+        "openai_chat" => openai_chat(base_url, model, input),
+        "openai_embeddings" => openai_embeddings(base_url, model, input),
+        "list_models" => list_models(base_url, input),
+        "pull_model" => pull_model(base_url, input),
+        "delete_model" => delete_model(base_url, input),
+        "copy_model" => copy_model(base_url, input),
+        "show_model" => show_model(base_url, input),
+        "running_models" => running_models(base_url, input),
+        "create_model" => create_model(base_url, input),
+        "session_create" => session_create(input),
+        "session_get" => session_get(input),
+        "session_reset" => session_reset(input),
+        "session_delete" => session_delete(input),
+        "dead_letters_list" => dead_letters_list(input),
+        "dead_letters_clear" => dead_letters_clear(input),
+        "cache_stats" => cache_stats(),
+        "cache_clear" => cache_clear(),
+        "metrics" => metrics_report(input),
+        "endpoints" => endpoints_status(base_url),
+        _ => Err(OllamaError::bad_request(format!("unknown action: {action}"))),
     }
 }
 
@@ -101,157 +262,1768 @@ pub fn process(Json(input): Json<DataType>) -> FnResult<Json<DataType>> {
 // Ollama API
 // =============================================================================
 
-fn chat(base_url: &str, model: &str, input: &DataType) -> FnResult<Json<DataType>> {
-    let messages = if let Some(msgs) = input.get("messages") {
+type ActionResult = Result<serde_json::Value, OllamaError>;
+
+/// Log through the host with configured secrets and URL credentials masked.
+fn log(message: &str) {
+    let config = magi_pdk::get_config().unwrap_or_default();
+    magi_pdk::log_info(&auth::redact(&config, message));
+}
+
+/// Shared HTTP path for every Ollama call: sends the request under the
+/// action's retry policy, maps transport failures to `unreachable` and
+/// non-2xx statuses to categorized errors. Returns the attempt count too.
+fn ollama_request(
+    action: &str,
+    method: &str,
+    endpoint: &Endpoint,
+    path: &str,
+    body: Option<&serde_json::Value>,
+) -> Result<(HttpResponse, u32), OllamaError> {
+    let config = magi_pdk::get_config().unwrap_or_default();
+    let url = format!("{}{path}", endpoint.url);
+    let mut req = HttpRequest::new(&url)
+        .with_method(method)
+        .with_header("Accept", "application/json");
+    if body.is_some() {
+        req = req.with_header("Content-Type", "application/json");
+    }
+    for (name, value) in auth::headers(&config, endpoint.auth.as_ref()) {
+        req = req.with_header(name, value);
+    }
+    let body_str = body.map(serde_json::to_string).transpose()?;
+
+    let policy = RetryPolicy::for_action(&config, action);
+    let (result, attempts) = policy.run(|| {
+        let resp = http::request::<String>(&req, body_str.clone())
+            .map_err(|e| OllamaError::unreachable(auth::redact(&config, &format!("{url}: {e}"))))?;
+        let status = resp.status_code();
+        if !(200..300).contains(&status) {
+            return Err(OllamaError::from_status(status, &resp.body()));
+        }
+        Ok(resp)
+    });
+
+    match result {
+        Ok(resp) => Ok((resp, attempts)),
+        Err(e) => Err(OllamaError {
+            attempts: Some(attempts),
+            ..e
+        }),
+    }
+}
+
+/// A response body for an inference action and where it came from.
+struct Reply {
+    raw: Vec<u8>,
+    attempts: u32,
+    cached: bool,
+    /// Endpoint that answered; `None` for cache hits.
+    endpoint: Option<String>,
+    /// Where a fresh reply goes in the cache once the caller accepts it.
+    cache_slot: Option<(CacheSettings, String)>,
+}
+
+impl Reply {
+    /// Cache a fresh reply. Callers do this only after the response passed
+    /// their own checks, so a failure is never replayed.
+    fn keep(&self) {
+        let Some((settings, key)) = &self.cache_slot else {
+            return;
+        };
+        // Ollama can also report an error inside a successful response.
+        if parse_ndjson(&self.raw).is_ok_and(|chunks| stream_error(&chunks).is_ok()) {
+            if let Err(e) = cache::put(settings, key, &self.raw) {
+                log(&format!("cache store failed: {}", e.message));
+            }
+        }
+    }
+}
+
+/// POST a native inference body for `action` to one endpoint through the
+/// configured backend. OpenAI-compatible replies come back translated into
+/// Ollama's shape.
+fn backend_request(
+    action: &str,
+    backend: Backend,
+    endpoint: &Endpoint,
+    body: &serde_json::Value,
+) -> Result<(Vec<u8>, u32), OllamaError> {
+    if backend == Backend::OllamaNative {
+        let path = backend::native_path(action);
+        let (resp, attempts) = ollama_request(action, "POST", endpoint, path, Some(body))?;
+        return Ok((resp.body(), attempts));
+    }
+    let (path, request) = backend::to_openai(action, body)?;
+    let (resp, attempts) = ollama_request(action, "POST", endpoint, path, Some(&request))?;
+    let reply: serde_json::Value = serde_json::from_slice(&resp.body())?;
+    let native = backend::from_openai(action, &reply)?;
+    Ok((serde_json::to_vec(&native)?, attempts))
+}
+
+/// Send an inference body to the best endpoint for its model, failing over
+/// to the next candidate when a host is down or lacks the model. `pinned`
+/// names a single endpoint to use instead of routing.
+fn routed_request(
+    action: &str,
+    base_url: &str,
+    body: &serde_json::Value,
+    pinned: Option<&str>,
+) -> Result<Reply, OllamaError> {
+    let config = magi_pdk::get_config().unwrap_or_default();
+    let backend = Backend::from_config(&config)?;
+    let candidates = match pinned {
+        Some(name) => vec![router::find(&config, base_url, name)?],
+        None => {
+            let probe = |endpoint: &Endpoint, path: &str| {
+                let (resp, _) = ollama_request("probe", "GET", endpoint, path, None).ok()?;
+                response_json(&resp).ok()
+            };
+            let model = body.get("model").and_then(|v| v.as_str()).unwrap_or("");
+            router::plan(&config, base_url, model, &probe)?
+        }
+    };
+
+    let (raw, attempts, endpoint) = fail_over(&candidates, |endpoint| {
+        backend_request(action, backend, endpoint, body)
+    })?;
+    Ok(Reply {
+        raw,
+        attempts,
+        cached: false,
+        endpoint: Some(endpoint),
+        cache_slot: None,
+    })
+}
+
+/// Try `send` against each candidate in turn, moving on when a host is down
+/// or lacks the model, and keep each endpoint's health up to date. Returns
+/// the result, the attempts spent across hosts and the endpoint that answered.
+fn fail_over<T>(
+    candidates: &[Endpoint],
+    mut send: impl FnMut(&Endpoint) -> Result<(T, u32), OllamaError>,
+) -> Result<(T, u32, String), OllamaError> {
+    let mut attempts = 0;
+    let mut last_error = None;
+    for endpoint in candidates {
+        match send(endpoint) {
+            Ok((value, tries)) => {
+                if let Err(e) = router::record_success(endpoint) {
+                    log(&format!("endpoint health not saved: {}", e.message));
+                }
+                return Ok((value, attempts + tries, endpoint.name.clone()));
+            }
+            Err(e) => {
+                attempts += e.attempts.unwrap_or(1);
+                match e.category {
+                    Category::Unreachable | Category::ServerError => {
+                        if let Err(store_err) = router::record_failure(endpoint, &e) {
+                            log(&format!(
+                                "endpoint health not saved: {}",
+                                store_err.message
+                            ));
+                        }
+                    }
+                    // Another host may carry the model.
+                    Category::ModelNotFound => {}
+                    _ => return Err(e),
+                }
+                last_error = Some(e);
+            }
+        }
+    }
+    let e = last_error.expect("router yields at least one endpoint");
+    Err(OllamaError {
+        attempts: Some(attempts),
+        ..e
+    })
+}
+
+/// `routed_request` behind the response cache. Hits report no attempts;
+/// fresh replies are stored when the caller calls `Reply::keep`.
+fn cached_request(
+    action: &str,
+    base_url: &str,
+    body: &serde_json::Value,
+    input: &DataType,
+) -> Result<Reply, OllamaError> {
+    let config = magi_pdk::get_config().unwrap_or_default();
+    let pinned = input.get("endpoint").and_then(|v| v.as_str());
+    let requested = input.get("cache").and_then(|v| v.as_bool());
+    let Some(settings) = CacheSettings::resolve(&config, action, requested) else {
+        return routed_request(action, base_url, body, pinned);
+    };
+
+    let key = cache::key(action, body);
+    match cache::get(&settings, &key) {
+        Ok(Some(raw)) => {
+            return Ok(Reply {
+                raw,
+                attempts: 0,
+                cached: true,
+                endpoint: None,
+                cache_slot: None,
+            })
+        }
+        Ok(None) => {}
+        Err(e) => log(&format!("cache lookup failed: {}", e.message)),
+    }
+    let mut reply = routed_request(action, base_url, body, pinned)?;
+    reply.cache_slot = Some((settings, key));
+    Ok(reply)
+}
+
+/// The host for actions that change one Ollama's models: the endpoint named
+/// by the request's `endpoint`, else the first configured one.
+fn primary_endpoint(base_url: &str, input: &DataType) -> Result<Endpoint, OllamaError> {
+    let config = magi_pdk::get_config().unwrap_or_default();
+    match input.get("endpoint").and_then(|v| v.as_str()) {
+        Some(name) => router::find(&config, base_url, name),
+        None => Ok(router::configured(&config, base_url)?.swap_remove(0)),
+    }
+}
+
+/// Send a read-only request to the pinned endpoint, or to every endpoint in
+/// turn until one answers. Returns the response, attempts and endpoint name.
+fn read_request(
+    action: &str,
+    method: &str,
+    path: &str,
+    body: Option<&serde_json::Value>,
+    base_url: &str,
+    input: &DataType,
+) -> Result<(HttpResponse, u32, String), OllamaError> {
+    let config = magi_pdk::get_config().unwrap_or_default();
+    let candidates = match input.get("endpoint").and_then(|v| v.as_str()) {
+        Some(name) => vec![router::find(&config, base_url, name)?],
+        None => router::hosts(&config, base_url)?,
+    };
+    fail_over(&candidates, |endpoint| {
+        ollama_request(action, method, endpoint, path, body)
+    })
+}
+
+/// Whether a result was served from the response cache.
+fn is_cached(result: &serde_json::Value) -> bool {
+    result["cached"].as_bool() == Some(true)
+}
+
+fn response_json(resp: &HttpResponse) -> ActionResult {
+    Ok(serde_json::from_slice(&resp.body())?)
+}
+
+fn chat(base_url: &str, model: &str, input: &DataType) -> ActionResult {
+    let system = input
+        .get("system")
+        .and_then(|v| v.as_str())
+        .unwrap_or("You are a helpful assistant.");
+    let mut session = input
+        .get("session_id")
+        .and_then(|v| v.as_str())
+        .filter(|id| !id.is_empty())
+        .map(|id| Session::load_or_create(id, Some(system)));
+
+    let mut messages = if let Some(msgs) = input.get("messages") {
         msgs.to_json()
     } else if let Some(prompt) = input.get("prompt").and_then(|v| v.as_str()) {
-        let system = input
-            .get("system")
-            .and_then(|v| v.as_str())
-            .unwrap_or("You are a helpful assistant.");
-        json!([
-            {"role": "system", "content": system},
-            {"role": "user", "content": prompt}
-        ])
+        let mut user = json!({"role": "user", "content": prompt});
+        if let Some(images) = input.get("images") {
+            user["images"] = images.to_json();
+        }
+        if session.is_some() {
+            json!([user])
+        } else {
+            json!([
+                {"role": "system", "content": system},
+                user
+            ])
+        }
     } else {
-        return Ok(Json(DataType::from_json(
-            json!({"error": "prompt or messages required"}),
-        )));
+        return Err(OllamaError::bad_request("prompt or messages required"));
     };
 
-    let use_model = input
-        .get("model")
-        .and_then(|v| v.as_str())
-        .unwrap_or(model);
+    validate_messages(&messages)?;
+    attach_message_images(&mut messages)?;
 
-    let body = json!({
+    // With a session, `messages` holds only the new turns; the stored
+    // history is sent in front of them and both are recorded afterwards.
+    let turns = messages.as_array().cloned().unwrap_or_default();
+    let history = match &session {
+        Some(session) => session.context(&turns),
+        None => turns.clone(),
+    };
+
+    let tools = input.get("tools").map(|v| v.to_json());
+    if let Some(tools) = &tools {
+        validate_tools(tools)?;
+    }
+
+    let format = input.get("format").map(|v| v.to_json());
+    if let Some(format) = &format {
+        check_format(format)?;
+    }
+
+    let options = request_options(input)?;
+
+    let think = input.get("think").map(|v| v.to_json());
+    if let Some(think) = &think {
+        reasoning::check(think)?;
+    }
+
+    let config = magi_pdk::get_config().unwrap_or_default();
+    let capability = if has_images(&history) {
+        Capability::Vision
+    } else {
+        Capability::Chat
+    };
+    let requested = input.get("model").and_then(|v| v.as_str());
+    let use_model = models::resolve(&config, requested, capability, model);
+    let use_model = use_model.as_str();
+
+    let settings = ContextSettings::resolve(
+        &config,
+        input.get("context").map(|v| v.to_json()).as_ref(),
+        options.as_ref(),
+    )?;
+    let fitted = context::fit(history, &settings);
+    let mut messages = fitted.messages;
+    let mut summary = None;
+    if settings.strategy == Strategy::Summarize && !fitted.dropped.is_empty() {
+        let summary_model = settings.summary_model.as_deref().map(|m| models::alias(&config, m));
+        let summary_model = summary_model.as_deref().unwrap_or(use_model);
+        let previous = session.as_ref().and_then(|s| s.summary.as_deref());
+        let text = summarize(base_url, summary_model, previous, &fitted.dropped)?;
+        messages.retain(|m| !context::is_summary(m));
+        let at = messages
+            .iter()
+            .position(|m| m.get("role").and_then(|v| v.as_str()) != Some("system"))
+            .unwrap_or(messages.len());
+        messages.insert(at, context::summary_message(&text));
+        summary = Some(text);
+    }
+    let context_meta = json!({
+        "strategy": settings.strategy.as_str(),
+        "budget": settings.budget(),
+        "estimated_tokens": messages.iter().map(context::estimate_tokens).sum::<u64>(),
+        "dropped": fitted.dropped.len(),
+        "summarized": summary.is_some()
+    });
+
+    let stream = wants_stream(input);
+    let mut body = json!({
         "model": use_model,
         "messages": messages,
-        "stream": false
+        "stream": stream
+    });
+    if let Some(tools) = tools {
+        body["tools"] = tools;
+    }
+    if let Some(format) = &format {
+        body["format"] = format.clone();
+    }
+    if let Some(options) = options {
+        body["options"] = options;
+    }
+    if let Some(think) = think {
+        body["think"] = think;
+    }
+
+    let reply = cached_request("chat", base_url, &body, input)?;
+
+    let mut result = if stream {
+        let chunks = parse_ndjson(&reply.raw)?;
+        stream_error(&chunks)?;
+        collect_stream(
+            &chunks,
+            "/message/content",
+            "/message/thinking",
+            "content",
+            use_model,
+        )
+    } else {
+        let data: serde_json::Value = serde_json::from_slice(&reply.raw)?;
+
+        let content = data
+            .pointer("/message/content")
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+
+        let tool_calls = data
+            .pointer("/message/tool_calls")
+            .cloned()
+            .unwrap_or(json!([]));
+
+        json!({
+            "content": content,
+            "thinking": data.pointer("/message/thinking").and_then(|v| v.as_str()),
+            "tool_calls": tool_calls,
+            "model": data.get("model").and_then(|v| v.as_str()).unwrap_or(use_model),
+            "done": data.get("done").and_then(|v| v.as_bool()).unwrap_or(true),
+            "done_reason": data.get("done_reason"),
+            "total_duration": data.get("total_duration"),
+            "eval_count": data.get("eval_count"),
+            "prompt_eval_count": data.get("prompt_eval_count"),
+            "usage": metrics::usage(&data)
+        })
+    };
+
+    separate_thinking(&mut result, "content");
+    if !stream {
+        // Rebuilt from the separated fields so no inline `<think>` text survives.
+        let mut message = json!({"role": "assistant", "content": result["content"]});
+        if result["tool_calls"].as_array().is_some_and(|calls| !calls.is_empty()) {
+            message["tool_calls"] = result["tool_calls"].clone();
+        }
+        if result["thinking"].is_string() {
+            message["thinking"] = result["thinking"].clone();
+        }
+        result["message"] = message;
+    }
+    if let Some(format) = &format {
+        apply_format(&mut result, "content", format)?;
+    }
+    reply.keep();
+    result["meta"] = json!({
+        "attempts": reply.attempts,
+        "endpoint": reply.endpoint,
+        "context": context_meta
     });
+    if reply.cached {
+        result["cached"] = json!(true);
+    }
+
+    if let Some(session) = &mut session {
+        if let Some(summary) = summary {
+            session.compact(summary, fitted.dropped.len());
+        }
+        let mut reply = json!({"role": "assistant", "content": result["content"]});
+        if result["tool_calls"].as_array().is_some_and(|calls| !calls.is_empty()) {
+            reply["tool_calls"] = result["tool_calls"].clone();
+        }
+        session.record(&turns, reply);
+        session.save()?;
+        result["session_id"] = json!(session.id);
+    }
 
-    let url = format!("{base_url}/api/chat");
-    let req = HttpRequest::new(&url)
-        .with_method("POST")
-        .with_header("Content-Type", "application/json");
-    let body_str = serde_json::to_string(&body)?;
-    let resp = http::request::<String>(&req, Some(body_str))?;
-    let data: serde_json::Value = serde_json::from_slice(&resp.body())?;
+    Ok(result)
+}
 
-    let content = data
+/// Compact dropped turns into a short summary with the given model.
+fn summarize(
+    base_url: &str,
+    model: &str,
+    previous: Option<&str>,
+    dropped: &[serde_json::Value],
+) -> Result<String, OllamaError> {
+    let body = json!({
+        "model": model,
+        "messages": context::summary_request(previous, dropped),
+        "stream": false,
+        "options": {"temperature": 0}
+    });
+    let reply = routed_request("chat", base_url, &body, None)?;
+    let data: serde_json::Value = serde_json::from_slice(&reply.raw)?;
+    Ok(data
         .pointer("/message/content")
         .and_then(|v| v.as_str())
-        .unwrap_or("");
+        .unwrap_or("")
+        .trim()
+        .to_string())
+}
 
-    Ok(Json(DataType::from_json(json!({
-        "content": content,
-        "model": data.get("model").and_then(|v| v.as_str()).unwrap_or(use_model),
-        "done": data.get("done").and_then(|v| v.as_bool()).unwrap_or(true),
-        "total_duration": data.get("total_duration"),
-        "eval_count": data.get("eval_count")
-    }))))
+/// Check chat history shape, including `tool` result messages fed back
+/// after a tool call.
+fn validate_messages(messages: &serde_json::Value) -> Result<(), String> {
+    let list = messages
+        .as_array()
+        .ok_or_else(|| "messages must be an array".to_string())?;
+    for (i, msg) in list.iter().enumerate() {
+        let role = msg.get("role").and_then(|v| v.as_str()).unwrap_or("");
+        match role {
+            "system" | "user" | "assistant" => {}
+            "tool" => {
+                if msg.get("content").and_then(|v| v.as_str()).is_none() {
+                    return Err(format!("messages[{i}]: tool message requires string content"));
+                }
+            }
+            _ => return Err(format!("messages[{i}]: unsupported role '{role}'")),
+        }
+    }
+    Ok(())
+}
+
+/// Check that `tools` is a list of JSON-schema function definitions.
+fn validate_tools(tools: &serde_json::Value) -> Result<(), String> {
+    let list = tools
+        .as_array()
+        .ok_or_else(|| "tools must be an array".to_string())?;
+    for (i, tool) in list.iter().enumerate() {
+        if tool.get("type").and_then(|v| v.as_str()) != Some("function") {
+            return Err(format!("tools[{i}]: type must be 'function'"));
+        }
+        if tool.pointer("/function/name").and_then(|v| v.as_str()).is_none() {
+            return Err(format!("tools[{i}]: function.name is required"));
+        }
+    }
+    Ok(())
 }
 
-fn generate(base_url: &str, model: &str, input: &DataType) -> FnResult<Json<DataType>> {
+fn generate(base_url: &str, model: &str, input: &DataType) -> ActionResult {
     let prompt = input
         .get("prompt")
         .and_then(|v| v.as_str())
         .unwrap_or("");
     if prompt.is_empty() {
-        return Ok(Json(DataType::from_json(
-            json!({"error": "prompt is required"}),
-        )));
+        return Err(OllamaError::bad_request("prompt is required"));
     }
 
-    let use_model = input
-        .get("model")
-        .and_then(|v| v.as_str())
-        .unwrap_or(model);
+    let has_images = input
+        .get("images")
+        .is_some_and(|v| v.to_json().as_array().is_some_and(|a| !a.is_empty()));
+    let capability = if has_images {
+        Capability::Vision
+    } else {
+        Capability::Chat
+    };
+    let requested = input.get("model").and_then(|v| v.as_str());
+    let config = magi_pdk::get_config().unwrap_or_default();
+    let use_model = models::resolve(&config, requested, capability, model);
+    let use_model = use_model.as_str();
 
-    let body = json!({
+    let stream = wants_stream(input);
+    let mut body = json!({
         "model": use_model,
         "prompt": prompt,
-        "stream": false
+        "stream": stream
     });
+    if let Some(suffix) = input.get("suffix").and_then(|v| v.as_str()) {
+        body["suffix"] = json!(suffix);
+    }
+    if let Some(images) = input.get("images") {
+        body["images"] = json!(resolve_images(&images.to_json(), max_image_bytes())?);
+    }
+    let format = input.get("format").map(|v| v.to_json());
+    if let Some(format) = &format {
+        check_format(format)?;
+        body["format"] = format.clone();
+    }
+    if let Some(options) = request_options(input)? {
+        body["options"] = options;
+    }
+    if let Some(think) = input.get("think").map(|v| v.to_json()) {
+        reasoning::check(&think)?;
+        body["think"] = think;
+    }
 
-    let url = format!("{base_url}/api/generate");
-    let req = HttpRequest::new(&url)
-        .with_method("POST")
-        .with_header("Content-Type", "application/json");
-    let body_str = serde_json::to_string(&body)?;
-    let resp = http::request::<String>(&req, Some(body_str))?;
-    let data: serde_json::Value = serde_json::from_slice(&resp.body())?;
+    let reply = cached_request("generate", base_url, &body, input)?;
 
-    Ok(Json(DataType::from_json(json!({
-        "response": data.get("response").and_then(|v| v.as_str()).unwrap_or(""),
-        "model": data.get("model").and_then(|v| v.as_str()).unwrap_or(use_model),
-        "done": data.get("done").and_then(|v| v.as_bool()).unwrap_or(true)
-    }))))
+    let mut result = if stream {
+        let chunks = parse_ndjson(&reply.raw)?;
+        stream_error(&chunks)?;
+        collect_stream(&chunks, "/response", "/thinking", "response", use_model)
+    } else {
+        let data: serde_json::Value = serde_json::from_slice(&reply.raw)?;
+        json!({
+            "response": data.get("response").and_then(|v| v.as_str()).unwrap_or(""),
+            "thinking": data.get("thinking").and_then(|v| v.as_str()),
+            "model": data.get("model").and_then(|v| v.as_str()).unwrap_or(use_model),
+            "done": data.get("done").and_then(|v| v.as_bool()).unwrap_or(true),
+            "total_duration": data.get("total_duration"),
+            "eval_count": data.get("eval_count"),
+            "prompt_eval_count": data.get("prompt_eval_count"),
+            "usage": metrics::usage(&data)
+        })
+    };
+
+    separate_thinking(&mut result, "response");
+    if let Some(format) = &format {
+        apply_format(&mut result, "response", format)?;
+    }
+    reply.keep();
+    result["meta"] = json!({"attempts": reply.attempts, "endpoint": reply.endpoint});
+    if reply.cached {
+        result["cached"] = json!(true);
+    }
+
+    Ok(result)
 }
 
-fn embeddings(base_url: &str, model: &str, input: &DataType) -> FnResult<Json<DataType>> {
-    let text = input.get("text").and_then(|v| v.as_str()).unwrap_or("");
-    if text.is_empty() {
-        return Ok(Json(DataType::from_json(
-            json!({"error": "text is required"}),
-        )));
+fn embeddings(base_url: &str, model: &str, input: &DataType) -> ActionResult {
+    let items = embedding_inputs(input)?;
+
+    let requested = input.get("model").and_then(|v| v.as_str());
+    let config = magi_pdk::get_config().unwrap_or_default();
+    let use_model = models::resolve(&config, requested, Capability::Embed, model);
+    let use_model = use_model.as_str();
+
+    let mut template = json!({"model": use_model});
+    if let Some(truncate) = input.get("truncate") {
+        template["truncate"] = json!(truncate
+            .as_bool()
+            .ok_or_else(|| OllamaError::bad_request("truncate must be a boolean"))?);
+    }
+    if let Some(dimensions) = input.get("dimensions") {
+        let dimensions = dimensions
+            .to_json()
+            .as_u64()
+            .filter(|d| *d > 0)
+            .ok_or_else(|| OllamaError::bad_request("dimensions must be a positive integer"))?;
+        template["dimensions"] = json!(dimensions);
+    }
+    if let Some(options) = request_options(input)? {
+        template["options"] = options;
     }
 
-    let body = json!({
-        "model": model,
-        "input": text
+    let batch_size = magi_pdk::get_config()
+        .unwrap_or_default()
+        .get("embed_batch_size")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(DEFAULT_EMBED_BATCH_SIZE)
+        .max(1) as usize;
+
+    let mut vectors = Vec::with_capacity(items.len());
+    let mut attempts = 0;
+    let mut cached = true;
+    let mut served_by = use_model.to_string();
+    let mut endpoint = None;
+    // Per-batch counts and durations, summed into one usage block.
+    let mut totals = json!({});
+
+    for batch in items.chunks(batch_size) {
+        let texts: Vec<&str> = batch.iter().map(|(_, text)| text.as_str()).collect();
+        let mut body = template.clone();
+        body["input"] = json!(texts);
+
+        let reply = cached_request("embeddings", base_url, &body, input)?;
+        attempts += reply.attempts;
+        cached &= reply.cached;
+        endpoint = reply.endpoint.clone().or(endpoint);
+        let data: serde_json::Value = serde_json::from_slice(&reply.raw)?;
+
+        let batch_vectors = data
+            .get("embeddings")
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default();
+        if batch_vectors.len() != batch.len() {
+            return Err(OllamaError::invalid_response(format!(
+                "expected {} embeddings, got {}",
+                batch.len(),
+                batch_vectors.len()
+            )));
+        }
+        reply.keep();
+        if let Some(m) = data.get("model").and_then(|v| v.as_str()) {
+            served_by = m.to_string();
+        }
+        for field in ["prompt_eval_count", "total_duration", "load_duration"] {
+            if let Some(n) = data.get(field).and_then(|v| v.as_u64()) {
+                totals[field] = json!(totals[field].as_u64().unwrap_or(0) + n);
+            }
+        }
+        vectors.extend(batch_vectors);
+    }
+
+    let results: Vec<_> = items
+        .iter()
+        .enumerate()
+        .map(|(index, (id, _))| json!({"index": index, "id": id}))
+        .collect();
+
+    let mut result = json!({
+        "embeddings": vectors,
+        "results": results,
+        "count": results.len(),
+        "model": served_by,
+        "usage": metrics::usage(&totals),
+        "meta": {
+            "attempts": attempts,
+            "endpoint": endpoint,
+            "batches": items.len().div_ceil(batch_size)
+        }
     });
+    if cached {
+        result["cached"] = json!(true);
+    }
+    Ok(result)
+}
 
-    let url = format!("{base_url}/api/embed");
-    let req = HttpRequest::new(&url)
-        .with_method("POST")
-        .with_header("Content-Type", "application/json");
-    let body_str = serde_json::to_string(&body)?;
-    let resp = http::request::<String>(&req, Some(body_str))?;
-    let data: serde_json::Value = serde_json::from_slice(&resp.body())?;
+/// Collect `(id, text)` pairs from `text`, or from `texts` given as strings
+/// or `{"id", "text"}` objects, with optional parallel `ids`.
+fn embedding_inputs(input: &DataType) -> Result<Vec<(serde_json::Value, String)>, OllamaError> {
+    if let Some(text) = input.get("text").and_then(|v| v.as_str()) {
+        if text.is_empty() {
+            return Err(OllamaError::bad_request("text is required"));
+        }
+        return Ok(vec![(json!(0), text.to_string())]);
+    }
 
-    Ok(Json(DataType::from_json(json!({
-        "embeddings": data.get("embeddings"),
-        "model": data.get("model").and_then(|v| v.as_str()).unwrap_or(model)
-    }))))
+    let texts = input
+        .get("texts")
+        .map(|v| v.to_json())
+        .ok_or_else(|| OllamaError::bad_request("text or texts required"))?;
+    let texts = texts
+        .as_array()
+        .filter(|list| !list.is_empty())
+        .ok_or_else(|| OllamaError::bad_request("texts must be a non-empty array"))?;
+    let ids = input.get("ids").map(|v| v.to_json());
+    let ids = ids.as_ref().and_then(|v| v.as_array());
+    if ids.is_some_and(|ids| ids.len() != texts.len()) {
+        return Err(OllamaError::bad_request("ids must be the same length as texts"));
+    }
+
+    let mut items = Vec::with_capacity(texts.len());
+    for (i, entry) in texts.iter().enumerate() {
+        let (id, text) = match entry {
+            serde_json::Value::String(text) => (None, text.as_str()),
+            serde_json::Value::Object(obj) => (
+                obj.get("id").cloned(),
+                obj.get("text").and_then(|v| v.as_str()).unwrap_or(""),
+            ),
+            _ => (None, ""),
+        };
+        if text.is_empty() {
+            return Err(OllamaError::bad_request(format!(
+                "texts[{i}]: expected a non-empty string or {{\"id\", \"text\"}}"
+            )));
+        }
+        let id = id
+            .or_else(|| ids.map(|ids| ids[i].clone()))
+            .unwrap_or(json!(i));
+        items.push((id, text.to_string()));
+    }
+    Ok(items)
 }
 
-fn list_models(base_url: &str) -> FnResult<Json<DataType>> {
-    let url = format!("{base_url}/api/tags");
-    let req = HttpRequest::new(&url)
-        .with_header("Accept", "application/json");
-    let resp = http::request::<String>(&req, None::<String>)?;
-    let data: serde_json::Value = serde_json::from_slice(&resp.body())?;
-    Ok(Json(DataType::from_json(data)))
+fn code(base_url: &str, model: &str, input: &DataType) -> ActionResult {
+    let task = input
+        .get("task")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    if task.is_empty() {
+        return Err(OllamaError::bad_request("task is required"));
+    }
+    let language = input.get("language").and_then(|v| v.as_str());
+    let existing = input.get("code").and_then(|v| v.as_str());
+    let constraints = match input.get("constraints").map(|v| v.to_json()) {
+        None => Vec::new(),
+        Some(serde_json::Value::String(c)) => vec![c],
+        Some(serde_json::Value::Array(list)) => list
+            .iter()
+            .map(|v| v.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| OllamaError::bad_request("constraints must be strings"))?,
+        Some(_) => return Err(OllamaError::bad_request("constraints must be strings")),
+    };
+
+    let config = magi_pdk::get_config().unwrap_or_default();
+    let code_model = models::resolve(&config, None, Capability::Code, model);
+
+    let mut request = json!({
+        "system": code::system_prompt(language),
+        "prompt": code::user_prompt(task, language, existing, &constraints),
+        "options": code_options(input)?
+    });
+    for key in ["model", "session_id", "context", "stream", "endpoint", "cache"] {
+        if let Some(value) = input.get(key) {
+            request[key] = value.to_json();
+        }
+    }
+
+    let mut result = chat(base_url, &code_model, &DataType::from_json(request))?;
+    let content = result["content"].as_str().unwrap_or("").to_string();
+    let (blocks, explanation) = code::extract_blocks(&content, language);
+    result["language"] = json!(language);
+    result["blocks"] = json!(blocks);
+    result["explanation"] = json!(explanation);
+    Ok(result)
+}
+
+/// The caller's `options` for a code action. Code benefits from
+/// near-deterministic sampling, so a low temperature is added unless the
+/// request or the config sets one.
+fn code_options(input: &DataType) -> Result<serde_json::Value, OllamaError> {
+    let mut options = match input.get("options").map(|v| v.to_json()) {
+        None | Some(serde_json::Value::Null) => json!({}),
+        Some(options @ serde_json::Value::Object(_)) => options,
+        Some(_) => return Err(OllamaError::bad_request("request options must be an object")),
+    };
+    let config = magi_pdk::get_config().unwrap_or_default();
+    if options.get("temperature").is_none() && config.pointer("/options/temperature").is_none() {
+        options["temperature"] = json!(CODE_TEMPERATURE);
+    }
+    Ok(options)
+}
+
+/// Fill-in-the-middle completion between `prefix` and `suffix`.
+fn complete(base_url: &str, model: &str, input: &DataType) -> ActionResult {
+    let prefix = required_str(input, "prefix")?;
+    let suffix = input
+        .get("suffix")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+
+    let config = magi_pdk::get_config().unwrap_or_default();
+    let code_model = models::resolve(&config, None, Capability::Code, model);
+
+    let mut options = code_options(input)?;
+    if let Some(max_tokens) = input.get("max_tokens") {
+        options["num_predict"] = max_tokens.to_json();
+    }
+    if let Some(stop) = input.get("stop") {
+        options["stop"] = stop.to_json();
+    }
+
+    let mut request = json!({
+        "prompt": prefix,
+        "suffix": suffix,
+        "options": options
+    });
+    for key in ["model", "endpoint", "cache"] {
+        if let Some(value) = input.get(key) {
+            request[key] = value.to_json();
+        }
+    }
+
+    let result = generate(base_url, &code_model, &DataType::from_json(request))?;
+    let raw = result["response"].as_str().unwrap_or("");
+    let mut completion = json!({
+        "completion": code::trim_completion(raw, suffix),
+        "model": result["model"],
+        "done": result["done"],
+        "usage": result["usage"],
+        "meta": result["meta"]
+    });
+    if let Some(cached) = result.get("cached") {
+        completion["cached"] = cached.clone();
+    }
+    Ok(completion)
 }
 
-fn poll_messages(base_url: &str, model: &str) -> FnResult<Json<DataType>> {
+fn list_models(base_url: &str, input: &DataType) -> ActionResult {
+    let (resp, attempts, endpoint) =
+        read_request("list_models", "GET", "/api/tags", None, base_url, input)?;
+    let mut data = response_json(&resp)?;
+    data["meta"] = json!({"attempts": attempts, "endpoint": endpoint});
+    Ok(data)
+}
+
+/// Resolve the request's `options` over the configured defaults.
+fn request_options(input: &DataType) -> Result<Option<serde_json::Value>, String> {
+    let config = magi_pdk::get_config().unwrap_or_default();
+    let request = input.get("options").map(|v| v.to_json());
+    options::resolve(&config, request.as_ref())
+}
+
+// =============================================================================
+// OpenAI compatibility
+// =============================================================================
+
+/// Chat completion in the OpenAI request and response shape, served by
+/// `chat` once per requested choice.
+fn openai_chat(base_url: &str, model: &str, input: &DataType) -> ActionResult {
+    let (request, n) = openai::chat_input(&input.to_json())?;
+    let request = DataType::from_json(request);
+    let results = (0..n)
+        .map(|_| chat(base_url, model, &request))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut response = openai::chat_response(&results, model);
+    if results.iter().all(is_cached) {
+        response["cached"] = json!(true);
+    }
+    Ok(response)
+}
+
+fn openai_embeddings(base_url: &str, model: &str, input: &DataType) -> ActionResult {
+    let (request, base64) = openai::embeddings_input(&input.to_json())?;
+    let result = embeddings(base_url, model, &DataType::from_json(request))?;
+    let encode: Option<fn(&[u8]) -> String> = base64.then_some(base64_encode);
+    let mut response = openai::embeddings_response(&result, encode);
+    if is_cached(&result) {
+        response["cached"] = json!(true);
+    }
+    Ok(response)
+}
+
+// =============================================================================
+// Agent messages
+// =============================================================================
+
+fn poll_messages(base_url: &str, model: &str) -> ActionResult {
     let messages = magi_pdk::agent_receive(10).unwrap_or_default();
+    let limit = magi_pdk::get_config()
+        .unwrap_or_default()
+        .get("dead_letter_limit")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(dead_letter::DEFAULT_LIMIT);
     let mut results = Vec::new();
+    let mut failed = 0;
 
     for msg in &messages {
         let from = msg.get("from").and_then(|v| v.as_str()).unwrap_or("unknown");
         let payload = msg.get("payload").cloned().unwrap_or(json!(null));
-        let prompt = payload
-            .get("prompt")
+        match handle_agent_message(base_url, model, from, &payload) {
+            Ok(summary) => results.push(summary),
+            Err(e) => {
+                failed += 1;
+                let _ = magi_pdk::agent_send(from, protocol::error_reply(&payload, e.to_json()));
+                // Refusals are deliberate, not failures worth retrying later.
+                let refused = matches!(e.category, Category::RateLimited | Category::Forbidden);
+                if !refused {
+                    if let Err(store_err) = dead_letter::push(from, &payload, &e, limit) {
+                        log(&format!(
+                            "dead letter not saved: {}",
+                            store_err.message
+                        ));
+                    }
+                }
+                results.push(json!({
+                    "from": from,
+                    "request_id": payload.get("request_id"),
+                    "error": e.to_json()
+                }));
+            }
+        }
+    }
+
+    Ok(json!({
+        "processed": results.len() - failed,
+        "failed": failed,
+        "results": results
+    }))
+}
+
+/// Run one agent request and deliver its reply, returning a summary.
+fn handle_agent_message(
+    base_url: &str,
+    model: &str,
+    from: &str,
+    payload: &serde_json::Value,
+) -> ActionResult {
+    let request = AgentRequest::parse(payload)?;
+    let config = magi_pdk::get_config().unwrap_or_default();
+    if !protocol::allowed(&config, &request.action) {
+        return Err(OllamaError::new(
+            Category::Forbidden,
+            format!("action not permitted for agents: {}", request.action),
+        ));
+    }
+    rate_limit::admit(&config, from)?;
+
+    let mut input = request.input();
+    confine_agent_input(from, &request, &mut input)?;
+    let input = DataType::from_json(input);
+    let mut response = dispatch_metered(&request.action, base_url, model, &input, from)?;
+    // A cache hit replays stored usage without running the model.
+    if !is_cached(&response) {
+        let tokens = response
+            .pointer("/usage/total_tokens")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0);
+        if let Err(e) = rate_limit::charge(from, tokens) {
+            log(&format!("token usage for {from} not recorded: {}", e.message));
+        }
+    }
+    if drop_thinking() {
+        strip_thinking(&mut response);
+    }
+
+    let field = if request.action == "generate" { "response" } else { "content" };
+    let content = response
+        .get(field)
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_string();
+    if request.streams() {
+        forward_stream(from, &response, &content, request.request_id.as_deref());
+    }
+    if !request.legacy {
+        // Streamed chunks were already delivered one by one.
+        if let Some(obj) = response.as_object_mut() {
+            obj.remove("chunks");
+        }
+        let _ = magi_pdk::agent_send(from, request.reply(response));
+    } else if !request.streams() {
+        let _ = magi_pdk::agent_send(from, json!({"response": content}));
+    }
+
+    Ok(json!({
+        "from": from,
+        "request_id": request.request_id,
+        "action": request.action,
+        "response": content
+    }))
+}
+
+/// Limit a peer's request to what it may touch: images must be sent inline
+/// rather than read from host paths, and sessions live under the sender's
+/// own `agent:<name>` namespace.
+fn confine_agent_input(
+    from: &str,
+    request: &AgentRequest,
+    input: &mut serde_json::Value,
+) -> Result<(), OllamaError> {
+    let reads_file = |images: &serde_json::Value| {
+        images.as_array().is_some_and(|list| {
+            list.iter().any(|image| {
+                image.get("path").is_some()
+                    || image.as_str().is_some_and(|s| s.starts_with("file://"))
+            })
+        })
+    };
+    let mut message_images = input["messages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|m| &m["images"]);
+    if reads_file(&input["images"]) || message_images.any(reads_file) {
+        return Err(OllamaError::new(
+            Category::Forbidden,
+            "agents must send images as base64 data, not file paths",
+        ));
+    }
+
+    let own = format!("agent:{}", from.replace('%', "%25").replace(':', "%3A"));
+    let is_own = |id: &str| id == own || id.starts_with(&format!("{own}:"));
+    match input.get("session_id").and_then(|v| v.as_str()) {
+        Some(id) if !id.is_empty() => {
+            let id = if is_own(id) { id.to_string() } else { format!("{own}:{id}") };
+            input["session_id"] = json!(id);
+        }
+        // Legacy prompts keep a per-sender conversation automatically.
+        _ if request.legacy => input["session_id"] = json!(own),
+        _ if request.action == "session_create" => {
+            input["session_id"] = json!(format!("{own}:{}", session::new_id()));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// =============================================================================
+// Dead letters
+// =============================================================================
+
+/// Optional `ids` filter for the dead-letter actions.
+fn dead_letter_ids(input: &DataType) -> Result<Option<Vec<u64>>, OllamaError> {
+    let Some(ids) = input.get("ids").map(|v| v.to_json()) else {
+        return Ok(None);
+    };
+    ids.as_array()
+        .and_then(|list| list.iter().map(|v| v.as_u64()).collect::<Option<Vec<_>>>())
+        .map(Some)
+        .ok_or_else(|| OllamaError::bad_request("ids must be an array of integers"))
+}
+
+fn dead_letters_list(input: &DataType) -> ActionResult {
+    let ids = dead_letter_ids(input)?;
+    let entries = dead_letter::list(ids.as_deref());
+    Ok(json!({"count": entries.len(), "dead_letters": entries}))
+}
+
+/// Re-run dead letters; successes are replied to and removed, failures
+/// stay with an updated error and attempt count.
+fn dead_letters_retry(base_url: &str, model: &str, input: &DataType) -> ActionResult {
+    let ids = dead_letter_ids(input)?;
+    let mut results = Vec::new();
+    let mut recovered = Vec::new();
+
+    for entry in dead_letter::list(ids.as_deref()) {
+        let id = entry["id"].as_u64().unwrap_or(0);
+        let from = entry["from"].as_str().unwrap_or("unknown");
+        match handle_agent_message(base_url, model, from, &entry["payload"]) {
+            Ok(_) => {
+                recovered.push(id);
+                results.push(json!({"id": id, "ok": true}));
+            }
+            Err(e) => {
+                dead_letter::record_failure(id, &e)?;
+                results.push(json!({"id": id, "ok": false, "error": e.to_json()}));
+            }
+        }
+    }
+    if !recovered.is_empty() {
+        dead_letter::remove(Some(&recovered))?;
+    }
+
+    Ok(json!({
+        "retried": results.len(),
+        "recovered": recovered.len(),
+        "results": results
+    }))
+}
+
+fn dead_letters_clear(input: &DataType) -> ActionResult {
+    let ids = dead_letter_ids(input)?;
+    let cleared = dead_letter::remove(ids.as_deref())?;
+    Ok(json!({"cleared": cleared}))
+}
+
+// =============================================================================
+// Response cache
+// =============================================================================
+
+fn cache_stats() -> ActionResult {
+    let config = magi_pdk::get_config().unwrap_or_default();
+    Ok(cache::stats(&config))
+}
+
+fn cache_clear() -> ActionResult {
+    let cleared = cache::clear()?;
+    Ok(json!({"cleared": cleared}))
+}
+
+// =============================================================================
+// Endpoints
+// =============================================================================
+
+fn endpoints_status(base_url: &str) -> ActionResult {
+    let config = magi_pdk::get_config().unwrap_or_default();
+    router::status(&config, base_url)
+}
+
+// =============================================================================
+// Metrics
+// =============================================================================
+
+/// Cumulative counters; `reset: true` clears them after reporting.
+fn metrics_report(input: &DataType) -> ActionResult {
+    let report = metrics::report();
+    if input.get("reset").and_then(|v| v.as_bool()).unwrap_or(false) {
+        metrics::reset()?;
+    }
+    Ok(report)
+}
+
+// =============================================================================
+// Model management
+// =============================================================================
+
+fn required_str<'a>(input: &'a DataType, key: &str) -> Result<&'a str, OllamaError> {
+    input
+        .get(key)
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| OllamaError::bad_request(format!("{key} is required")))
+}
+
+fn pull_model(base_url: &str, input: &DataType) -> ActionResult {
+    let config = magi_pdk::get_config().unwrap_or_default();
+    let model = models::alias(&config, required_str(input, "model")?);
+    let insecure = input
+        .get("insecure")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let body = json!({"model": model, "insecure": insecure, "stream": true});
+    let endpoint = primary_endpoint(base_url, input)?;
+    let (resp, _) = ollama_request("pull_model", "POST", &endpoint, "/api/pull", Some(&body))?;
+
+    let lines = parse_ndjson(&resp.body())?;
+    let mut statuses: Vec<String> = Vec::new();
+    let mut layers: Vec<serde_json::Value> = Vec::new();
+
+    for line in &lines {
+        let status = line.get("status").and_then(|v| v.as_str()).unwrap_or("");
+        if !status.is_empty() && statuses.last().map(String::as_str) != Some(status) {
+            statuses.push(status.to_string());
+        }
+        let Some(digest) = line.get("digest").and_then(|v| v.as_str()) else {
+            continue;
+        };
+        let total = line.get("total").and_then(|v| v.as_u64()).unwrap_or(0);
+        let completed = line.get("completed").and_then(|v| v.as_u64()).unwrap_or(0);
+        let percent = if total > 0 {
+            (completed as f64 / total as f64 * 100.0).min(100.0)
+        } else {
+            0.0
+        };
+        let layer = json!({
+            "digest": digest,
+            "total": total,
+            "completed": completed,
+            "percent": percent
+        });
+        match layers.iter_mut().find(|l| l["digest"] == digest) {
+            Some(existing) => *existing = layer,
+            None => layers.push(layer),
+        }
+    }
+
+    let progress = json!({"statuses": statuses, "layers": layers});
+    if let Err(e) = stream_error(&lines) {
+        return Err(e.with_details(progress));
+    }
+
+    let status = statuses.last().cloned().unwrap_or_default();
+    Ok(json!({
+        "model": model,
+        "status": status,
+        "success": status == "success",
+        "progress": progress
+    }))
+}
+
+fn delete_model(base_url: &str, input: &DataType) -> ActionResult {
+    let config = magi_pdk::get_config().unwrap_or_default();
+    let model = models::alias(&config, required_str(input, "model")?);
+
+    let body = json!({"model": model});
+    let endpoint = primary_endpoint(base_url, input)?;
+    ollama_request("delete_model", "DELETE", &endpoint, "/api/delete", Some(&body))?;
+    Ok(json!({"model": model, "status": "deleted", "success": true}))
+}
+
+fn copy_model(base_url: &str, input: &DataType) -> ActionResult {
+    let config = magi_pdk::get_config().unwrap_or_default();
+    let source = models::alias(&config, required_str(input, "source")?);
+    let destination = required_str(input, "destination")?;
+
+    let body = json!({"source": source, "destination": destination});
+    let endpoint = primary_endpoint(base_url, input)?;
+    ollama_request("copy_model", "POST", &endpoint, "/api/copy", Some(&body))?;
+    Ok(json!({
+        "model": destination,
+        "source": source,
+        "destination": destination,
+        "status": "copied",
+        "success": true
+    }))
+}
+
+fn show_model(base_url: &str, input: &DataType) -> ActionResult {
+    let config = magi_pdk::get_config().unwrap_or_default();
+    let model = models::alias(&config, required_str(input, "model")?);
+    let verbose = input
+        .get("verbose")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let body = json!({"model": model, "verbose": verbose});
+    let (resp, _, _) =
+        read_request("show_model", "POST", "/api/show", Some(&body), base_url, input)?;
+    let data = response_json(&resp)?;
+
+    Ok(json!({
+        "model": model,
+        "details": data.get("details"),
+        "capabilities": data.get("capabilities"),
+        "parameters": data.get("parameters"),
+        "template": data.get("template"),
+        "modelfile": data.get("modelfile"),
+        "model_info": data.get("model_info"),
+        "modified_at": data.get("modified_at")
+    }))
+}
+
+/// Fields a structured `create_model` spec may carry.
+const CREATE_SPEC_FIELDS: &[&str] = &[
+    "from",
+    "system",
+    "template",
+    "parameters",
+    "adapters",
+    "license",
+    "messages",
+    "quantize",
+];
+
+fn create_model(base_url: &str, input: &DataType) -> ActionResult {
+    let model = required_str(input, "model")?;
+
+    let mut body = if let Some(text) = input.get("modelfile").and_then(|v| v.as_str()) {
+        modelfile::parse(text)?
+    } else if let Some(spec) = input.get("spec") {
+        create_spec(&spec.to_json())?
+    } else {
+        return Err(OllamaError::bad_request("modelfile or spec required"));
+    };
+    if let Some(from) = body.get("from").and_then(|v| v.as_str()) {
+        let config = magi_pdk::get_config().unwrap_or_default();
+        body["from"] = json!(models::alias(&config, from));
+    }
+    body["model"] = json!(model);
+    body["stream"] = json!(true);
+
+    let endpoint = primary_endpoint(base_url, input)?;
+    let (resp, _) = ollama_request("create_model", "POST", &endpoint, "/api/create", Some(&body))?;
+
+    let lines = parse_ndjson(&resp.body())?;
+    let statuses: Vec<&str> = lines
+        .iter()
+        .filter_map(|line| line.get("status").and_then(|v| v.as_str()))
+        .collect();
+    if let Err(e) = stream_error(&lines) {
+        return Err(e.with_details(json!({"statuses": statuses})));
+    }
+
+    let status = statuses.last().copied().unwrap_or_default();
+    Ok(json!({
+        "model": model,
+        "status": status,
+        "success": status == "success",
+        "progress": {"statuses": statuses}
+    }))
+}
+
+/// Normalize a structured persona spec into an `/api/create` body.
+fn create_spec(spec: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let fields = spec
+        .as_object()
+        .ok_or_else(|| "spec must be an object".to_string())?;
+    let mut body = serde_json::Map::new();
+    for (key, value) in fields {
+        let key = if key == "base" { "from" } else { key.as_str() };
+        if !CREATE_SPEC_FIELDS.contains(&key) {
+            return Err(format!("unknown spec field '{key}'"));
+        }
+        body.insert(key.to_string(), value.clone());
+    }
+    if !body.get("from").is_some_and(|v| v.is_string()) {
+        return Err("spec.base (or spec.from) is required".to_string());
+    }
+    if body.get("parameters").is_some_and(|v| !v.is_object()) {
+        return Err("spec.parameters must be an object".to_string());
+    }
+    if body.get("adapters").is_some_and(|v| !v.is_object()) {
+        return Err("spec.adapters must map file names to blob digests".to_string());
+    }
+    Ok(serde_json::Value::Object(body))
+}
+
+fn running_models(base_url: &str, input: &DataType) -> ActionResult {
+    let (resp, _, _) = read_request("running_models", "GET", "/api/ps", None, base_url, input)?;
+    let data = response_json(&resp)?;
+    Ok(json!({
+        "models": data.get("models").cloned().unwrap_or(json!([]))
+    }))
+}
+
+// =============================================================================
+// Sessions
+// =============================================================================
+
+fn session_create(input: &DataType) -> ActionResult {
+    let id = match input.get("session_id").and_then(|v| v.as_str()) {
+        Some(id) if !id.is_empty() => id.to_string(),
+        _ => session::new_id(),
+    };
+    if Session::load(&id).is_some() {
+        return Err(OllamaError::bad_request(format!("session already exists: {id}")));
+    }
+    let system = input
+        .get("system")
+        .and_then(|v| v.as_str())
+        .unwrap_or("You are a helpful assistant.");
+
+    let mut session = Session::new(&id, Some(system));
+    session.save()?;
+    Ok(session.to_json())
+}
+
+fn existing_session(input: &DataType) -> Result<Session, OllamaError> {
+    let id = required_str(input, "session_id")?;
+    Session::load(id).ok_or_else(|| OllamaError::bad_request(format!("session not found: {id}")))
+}
+
+fn session_get(input: &DataType) -> ActionResult {
+    Ok(existing_session(input)?.to_json())
+}
+
+fn session_reset(input: &DataType) -> ActionResult {
+    let mut session = existing_session(input)?;
+    session.reset();
+    if let Some(system) = input.get("system").and_then(|v| v.as_str()) {
+        session.system = Some(system.to_string());
+    }
+    session.save()?;
+    Ok(session.to_json())
+}
+
+fn session_delete(input: &DataType) -> ActionResult {
+    let session = existing_session(input)?;
+    Session::delete(&session.id)?;
+    Ok(json!({"session_id": session.id, "deleted": true}))
+}
+
+// =============================================================================
+// Structured output
+// =============================================================================
+
+/// Accept either `"json"` or a JSON schema object for Ollama's `format`.
+fn check_format(format: &serde_json::Value) -> Result<(), String> {
+    match format {
+        serde_json::Value::String(s) if s == "json" => Ok(()),
+        serde_json::Value::Object(_) => Ok(()),
+        _ => Err("format must be \"json\" or a JSON schema object".to_string()),
+    }
+}
+
+/// Parse the generated text under `field` and, for schema formats, validate
+/// it. Sets `data` on success; otherwise fails with `invalid_response`
+/// whose details carry the violations and the raw text.
+fn apply_format(
+    result: &mut serde_json::Value,
+    field: &str,
+    format: &serde_json::Value,
+) -> Result<(), OllamaError> {
+    let text = result.get(field).and_then(|v| v.as_str()).unwrap_or("");
+    let invalid = |message: &str, violations: Vec<String>| {
+        OllamaError::invalid_response(message)
+            .with_details(json!({"violations": violations, "content": text}))
+    };
+    let parsed: serde_json::Value = serde_json::from_str(text.trim())
+        .map_err(|e| invalid("response is not valid JSON", vec![e.to_string()]))?;
+
+    if format.is_object() {
+        let errors = schema::validate(&parsed, format);
+        if !errors.is_empty() {
+            return Err(invalid("response does not match schema", errors));
+        }
+    }
+
+    result["data"] = parsed;
+    Ok(())
+}
+
+// =============================================================================
+// Streaming
+// =============================================================================
+//
+// The host's HTTP call is blocking: Ollama's NDJSON body arrives in one piece
+// once generation has finished. `stream` therefore does not reduce latency;
+// it only changes the reply format to a list of deltas (forwarded to agents
+// as separate messages) followed by a summary chunk.
+
+/// Deltas are merged until they reach this many bytes, so replies carry a
+/// handful of chunks rather than one per token.
+const STREAM_CHUNK_BYTES: usize = 512;
+
+fn wants_stream(input: &DataType) -> bool {
+    input
+        .get("stream")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+/// Split an Ollama NDJSON response body into its individual JSON chunks.
+fn parse_ndjson(body: &[u8]) -> Result<Vec<serde_json::Value>, OllamaError> {
+    let text = String::from_utf8_lossy(body);
+    let mut chunks = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        chunks.push(serde_json::from_str(line)?);
+    }
+    Ok(chunks)
+}
+
+/// Surface an `error` line Ollama emitted part-way through a stream.
+fn stream_error(chunks: &[serde_json::Value]) -> Result<(), OllamaError> {
+    match chunks
+        .iter()
+        .find_map(|c| c.get("error").and_then(|v| v.as_str()))
+    {
+        Some(message) => Err(OllamaError::from_stream(message)),
+        None => Ok(()),
+    }
+}
+
+/// Fold streamed chunks into a list of partial deltas, merged up to
+/// `STREAM_CHUNK_BYTES`, followed by a final summary chunk, alongside the
+/// fully assembled text under `field`.
+fn collect_stream(
+    chunks: &[serde_json::Value],
+    pointer: &str,
+    thinking_pointer: &str,
+    field: &str,
+    model: &str,
+) -> serde_json::Value {
+    let mut text = String::new();
+    let mut thinking = String::new();
+    let mut tool_calls = Vec::new();
+    let mut partials = Vec::new();
+    // Delta being merged: its key (`delta` or `thinking_delta`) and text.
+    let mut pending: Option<(&str, String)> = None;
+    let flush = |pending: &mut Option<(&str, String)>, partials: &mut Vec<_>| {
+        if let Some((key, text)) = pending.take() {
+            partials.push(json!({key: text, "done": false}));
+        }
+    };
+
+    for chunk in chunks {
+        let delta = chunk.pointer(pointer).and_then(|v| v.as_str()).unwrap_or("");
+        let thought = chunk
+            .pointer(thinking_pointer)
             .and_then(|v| v.as_str())
             .unwrap_or("");
+        thinking.push_str(thought);
+        text.push_str(delta);
+        for (key, piece) in [("thinking_delta", thought), ("delta", delta)] {
+            if piece.is_empty() {
+                continue;
+            }
+            if pending.as_ref().is_some_and(|(k, _)| *k != key) {
+                flush(&mut pending, &mut partials);
+            }
+            let buffer = &mut pending.get_or_insert_with(|| (key, String::new())).1;
+            buffer.push_str(piece);
+            if buffer.len() >= STREAM_CHUNK_BYTES {
+                flush(&mut pending, &mut partials);
+            }
+        }
+        if let Some(calls) = chunk
+            .pointer("/message/tool_calls")
+            .and_then(|v| v.as_array())
+        {
+            flush(&mut pending, &mut partials);
+            tool_calls.extend(calls.iter().cloned());
+            partials.push(json!({"tool_calls": calls, "done": false}));
+        }
+    }
+    flush(&mut pending, &mut partials);
+
+    let last = chunks
+        .iter()
+        .rev()
+        .find(|c| c.get("done").and_then(|v| v.as_bool()).unwrap_or(false));
+    let model = last
+        .or(chunks.first())
+        .and_then(|c| c.get("model"))
+        .and_then(|v| v.as_str())
+        .unwrap_or(model);
+
+    partials.push(json!({
+        "done": last.is_some(),
+        "eval_count": last.and_then(|c| c.get("eval_count")),
+        "prompt_eval_count": last.and_then(|c| c.get("prompt_eval_count")),
+        "total_duration": last.and_then(|c| c.get("total_duration")),
+        "load_duration": last.and_then(|c| c.get("load_duration")),
+        "prompt_eval_duration": last.and_then(|c| c.get("prompt_eval_duration")),
+        "eval_duration": last.and_then(|c| c.get("eval_duration"))
+    }));
+
+    json!({
+        field: text,
+        "thinking": (!thinking.is_empty()).then_some(thinking),
+        "tool_calls": tool_calls,
+        "model": model,
+        "done": last.is_some(),
+        "total_duration": last.and_then(|c| c.get("total_duration")),
+        "eval_count": last.and_then(|c| c.get("eval_count")),
+        "prompt_eval_count": last.and_then(|c| c.get("prompt_eval_count")),
+        "usage": metrics::usage(last.unwrap_or(&json!({}))),
+        "stream": true,
+        "chunks": partials
+    })
+}
 
-        if !prompt.is_empty() {
-            let input = DataType::from_json(json!({"prompt": prompt}));
-            if let Ok(Json(response)) = chat(base_url, model, &input) {
-                let content = response
-                    .get("content")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                let _ = magi_pdk::agent_send(from, json!({"response": content}));
-                results.push(json!({"from": from, "response": content}));
+/// Relay the merged deltas to the requesting agent, tagged with the request
+/// id when there is one; the last chunk carries the full reply. All of them
+/// go out after generation has finished (see the note above).
+/// Reasoning deltas are withheld when `drop_thinking` is configured.
+fn forward_stream(
+    to: &str,
+    response: &serde_json::Value,
+    content: &str,
+    request_id: Option<&str>,
+) {
+    let chunks = response
+        .get("chunks")
+        .and_then(|v| v.as_array())
+        .cloned()
+        .unwrap_or_default();
+    let hide = drop_thinking();
+    let mut filter = reasoning::StreamFilter::default();
+    let last = chunks.len().saturating_sub(1);
+    for (i, mut chunk) in chunks.into_iter().enumerate() {
+        if i == last {
+            chunk["response"] = json!(content);
+        } else if hide {
+            if chunk.get("thinking_delta").is_some() {
+                continue;
+            }
+            if let Some(delta) = chunk.get("delta").and_then(|v| v.as_str()) {
+                let visible = filter.visible(delta);
+                if visible.is_empty() {
+                    continue;
+                }
+                chunk["delta"] = json!(visible);
             }
         }
+        if let Some(id) = request_id {
+            chunk["request_id"] = json!(id);
+        }
+        let _ = magi_pdk::agent_send(to, chunk);
     }
+}
 
-    Ok(Json(DataType::from_json(
-        json!({"processed": results.len(), "results": results}),
-    )))
+// =============================================================================
+// Reasoning
+// =============================================================================
+
+/// Move inline `<think>` reasoning out of `field` into `thinking` when the
+/// server did not already split it.
+fn separate_thinking(result: &mut serde_json::Value, field: &str) {
+    let text = result[field].as_str().unwrap_or("");
+    if !text.contains("<think>") && !text.contains("</think>") {
+        return;
+    }
+    let (inline, content) = reasoning::split_inline(text);
+    let thinking = match result["thinking"].as_str() {
+        Some(existing) if !existing.is_empty() => format!("{existing}\n\n{inline}"),
+        _ => inline,
+    };
+    result[field] = json!(content);
+    result["thinking"] = json!(thinking);
+}
+
+/// Remove reasoning from a result before it leaves for another agent.
+fn strip_thinking(result: &mut serde_json::Value) {
+    if let Some(obj) = result.as_object_mut() {
+        obj.remove("thinking");
+    }
+    if let Some(message) = result.get_mut("message").and_then(|v| v.as_object_mut()) {
+        message.remove("thinking");
+    }
+    if let Some(chunks) = result.get_mut("chunks").and_then(|v| v.as_array_mut()) {
+        let mut filter = reasoning::StreamFilter::default();
+        chunks.retain_mut(|chunk| {
+            if chunk.get("thinking_delta").is_some() {
+                return false;
+            }
+            if let Some(delta) = chunk.get("delta").and_then(|v| v.as_str()) {
+                let visible = filter.visible(delta);
+                if visible.is_empty() {
+                    return false;
+                }
+                chunk["delta"] = json!(visible);
+            }
+            true
+        });
+    }
+}
+
+fn drop_thinking() -> bool {
+    magi_pdk::get_config()
+        .unwrap_or_default()
+        .get("drop_thinking")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+// =============================================================================
+// Images
+// =============================================================================
+
+fn max_image_bytes() -> u64 {
+    magi_pdk::get_config()
+        .unwrap_or_default()
+        .get("max_image_bytes")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(DEFAULT_MAX_IMAGE_BYTES)
+}
+
+/// Whether any message carries images, so a vision model is needed.
+fn has_images(messages: &[serde_json::Value]) -> bool {
+    messages
+        .iter()
+        .any(|m| m["images"].as_array().is_some_and(|images| !images.is_empty()))
+}
+
+/// Replace every message's `images` entries with validated base64 payloads.
+fn attach_message_images(messages: &mut serde_json::Value) -> Result<(), String> {
+    let limit = max_image_bytes();
+    let Some(list) = messages.as_array_mut() else {
+        return Ok(());
+    };
+    for (i, msg) in list.iter_mut().enumerate() {
+        if let Some(images) = msg.get("images") {
+            let resolved =
+                resolve_images(images, limit).map_err(|e| format!("messages[{i}]: {e}"))?;
+            msg["images"] = json!(resolved);
+        }
+    }
+    Ok(())
+}
+
+/// Turn a list of image references into base64 strings Ollama accepts.
+///
+/// Each entry is either a base64 string (optionally a `data:` URI), a
+/// `file://` path, or an object `{"path": "..."}` read through the host.
+fn resolve_images(images: &serde_json::Value, limit: u64) -> Result<Vec<String>, String> {
+    let list = images
+        .as_array()
+        .ok_or_else(|| "images must be an array".to_string())?;
+    let mut out = Vec::with_capacity(list.len());
+    for (i, image) in list.iter().enumerate() {
+        let path = image
+            .get("path")
+            .and_then(|v| v.as_str())
+            .or_else(|| image.as_str().and_then(|s| s.strip_prefix("file://")));
+        let encoded = if let Some(path) = path {
+            let bytes = std::fs::read(path)
+                .map_err(|e| format!("images[{i}]: cannot read {path}: {e}"))?;
+            if bytes.len() as u64 > limit {
+                return Err(format!(
+                    "images[{i}]: {} bytes exceeds limit of {limit}",
+                    bytes.len()
+                ));
+            }
+            base64_encode(&bytes)
+        } else if let Some(data) = image.as_str() {
+            let data = match data.split_once(";base64,") {
+                Some((prefix, rest)) if prefix.starts_with("data:") => rest,
+                _ => data,
+            };
+            let data = data.trim();
+            if data.is_empty() || !data.bytes().all(is_base64_byte) {
+                return Err(format!("images[{i}]: not valid base64"));
+            }
+            let decoded = data.len() as u64 / 4 * 3;
+            if decoded > limit {
+                return Err(format!(
+                    "images[{i}]: ~{decoded} bytes exceeds limit of {limit}"
+                ));
+            }
+            data.to_string()
+        } else {
+            return Err(format!("images[{i}]: expected base64 string or {{\"path\": ...}}"));
+        };
+        out.push(encoded);
+    }
+    Ok(out)
+}
+
+fn is_base64_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/' | b'=')
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        out.push(ALPHABET[(n >> 18) as usize & 63] as char);
+        out.push(ALPHABET[(n >> 12) as usize & 63] as char);
+        out.push(if chunk.len() > 1 {
+            ALPHABET[(n >> 6) as usize & 63] as char
+        } else {
+            '='
+        });
+        out.push(if chunk.len() > 2 {
+            ALPHABET[n as usize & 63] as char
+        } else {
+            '='
+        });
+    }
+    out
 }
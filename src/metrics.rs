@@ -0,0 +1,186 @@
+//! Per-response usage figures and cumulative metrics in plugin storage.
+//!
+//! Ollama reports token counts and nanosecond durations on the final
+//! response object; `usage` normalizes those for callers, and `record`
+//! folds each inference request into totals per model and per sender.
+
+use std::time::Duration;
+
+use serde_json::{json, Map, Value};
+
+use crate::error::OllamaError;
+use crate::store;
+
+const KEY: &str = "metrics";
+/// Actions that drive inference and are therefore metered.
+const TRACKED: &[&str] = &[
+    "chat",
+    "generate",
+    "embeddings",
+    "code",
+    "complete",
+    "openai_chat",
+    "openai_embeddings",
+];
+/// Latency samples kept per bucket for percentile estimates.
+const SAMPLE_LIMIT: usize = 256;
+
+/// Usage block for a final Ollama response object. Durations stay in
+/// nanoseconds, as Ollama reports them.
+pub fn usage(data: &Value) -> Value {
+    let int = |name: &str| data.get(name).and_then(|v| v.as_u64());
+    let prompt = int("prompt_eval_count");
+    let completion = int("eval_count");
+    let rate = |tokens: Option<u64>, nanos: Option<u64>| match (tokens, nanos) {
+        (Some(t), Some(ns)) if ns > 0 => Some(t as f64 * 1e9 / ns as f64),
+        _ => None,
+    };
+    json!({
+        "prompt_tokens": prompt,
+        "completion_tokens": completion,
+        "total_tokens": prompt.unwrap_or(0) + completion.unwrap_or(0),
+        "total_duration": int("total_duration"),
+        "load_duration": int("load_duration"),
+        "prompt_eval_duration": int("prompt_eval_duration"),
+        "eval_duration": int("eval_duration"),
+        "prompt_tokens_per_second": rate(prompt, int("prompt_eval_duration")),
+        "tokens_per_second": rate(completion, int("eval_duration"))
+    })
+}
+
+pub fn tracks(action: &str) -> bool {
+    TRACKED.contains(&action)
+}
+
+fn load() -> Value {
+    store::load(KEY).unwrap_or_else(|| json!({"since": store::now()}))
+}
+
+fn add(bucket: &mut Value, name: &str, amount: u64) {
+    bucket[name] = json!(bucket[name].as_u64().unwrap_or(0) + amount);
+}
+
+fn update(bucket: &mut Value, result: Result<&Value, &OllamaError>, elapsed_ms: u64) {
+    add(bucket, "requests", 1);
+    match result {
+        Ok(response) => {
+            let tokens = |name: &str| {
+                response
+                    .pointer(&format!("/usage/{name}"))
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(0)
+            };
+            // Cache hits carry the stored usage, but no tokens were spent.
+            if response["cached"].as_bool() == Some(true) {
+                add(bucket, "cached", 1);
+            } else {
+                add(bucket, "prompt_tokens", tokens("prompt_tokens"));
+                add(bucket, "completion_tokens", tokens("completion_tokens"));
+            }
+        }
+        Err(e) => {
+            if !bucket["errors"].is_object() {
+                bucket["errors"] = json!({});
+            }
+            add(&mut bucket["errors"], e.category.as_str(), 1);
+        }
+    }
+    if !bucket["latencies_ms"].is_array() {
+        bucket["latencies_ms"] = json!([]);
+    }
+    let samples = bucket["latencies_ms"]
+        .as_array_mut()
+        .expect("latencies_ms is an array");
+    samples.push(json!(elapsed_ms));
+    if samples.len() > SAMPLE_LIMIT {
+        let excess = samples.len() - SAMPLE_LIMIT;
+        samples.drain(..excess);
+    }
+}
+
+/// Fold one metered request into the totals and its model and sender.
+pub fn record(
+    sender: &str,
+    model: &str,
+    result: Result<&Value, &OllamaError>,
+    elapsed: Duration,
+) -> Result<(), OllamaError> {
+    let mut state = load();
+    let elapsed_ms = elapsed.as_millis() as u64;
+    update(&mut state["totals"], result, elapsed_ms);
+    update(&mut state["models"][model], result, elapsed_ms);
+    update(&mut state["senders"][sender], result, elapsed_ms);
+    store::save(KEY, &state)
+}
+
+/// A stored bucket with its raw samples replaced by percentiles.
+fn summarize(bucket: &Value) -> Value {
+    let mut samples: Vec<u64> = bucket["latencies_ms"]
+        .as_array()
+        .map(|s| s.iter().filter_map(|v| v.as_u64()).collect())
+        .unwrap_or_default();
+    samples.sort_unstable();
+    let percentile = |p: usize| {
+        (!samples.is_empty()).then(|| samples[(samples.len() * p).div_ceil(100).max(1) - 1])
+    };
+
+    let mut out = bucket.clone();
+    if let Some(obj) = out.as_object_mut() {
+        obj.remove("latencies_ms");
+        obj.entry("errors").or_insert_with(|| json!({}));
+    }
+    out["error_count"] = json!(bucket["errors"]
+        .as_object()
+        .map_or(0, |e| e.values().filter_map(|v| v.as_u64()).sum::<u64>()));
+    out["latency_ms"] = json!({
+        "p50": percentile(50),
+        "p90": percentile(90),
+        "p99": percentile(99),
+        "max": samples.last(),
+        "samples": samples.len()
+    });
+    out
+}
+
+fn summarize_all(buckets: &Value) -> Value {
+    let map: Map<String, Value> = buckets
+        .as_object()
+        .map(|m| m.iter().map(|(k, v)| (k.clone(), summarize(v))).collect())
+        .unwrap_or_default();
+    Value::Object(map)
+}
+
+/// Cumulative metrics for the `metrics` action.
+pub fn report() -> Value {
+    let state = load();
+    json!({
+        "since": state["since"],
+        "totals": summarize(&state["totals"]),
+        "models": summarize_all(&state["models"]),
+        "senders": summarize_all(&state["senders"])
+    })
+}
+
+pub fn reset() -> Result<(), OllamaError> {
+    store::remove(KEY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_hits_count_no_tokens() {
+        let usage = json!({"usage": {"prompt_tokens": 10, "completion_tokens": 5}});
+        let mut hit = usage.clone();
+        hit["cached"] = json!(true);
+
+        let mut bucket = json!({});
+        update(&mut bucket, Ok(&usage), 20);
+        update(&mut bucket, Ok(&hit), 1);
+        assert_eq!(bucket["requests"], 2);
+        assert_eq!(bucket["cached"], 1);
+        assert_eq!(bucket["prompt_tokens"], 10);
+        assert_eq!(bucket["completion_tokens"], 5);
+    }
+}
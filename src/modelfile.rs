@@ -0,0 +1,210 @@
+//! Modelfile parsing for `create_model`.
+//!
+//! Ollama's `/api/create` takes a structured spec (`from`, `system`,
+//! `parameters`, ...), so raw Modelfile text is translated into that shape
+//! before it is sent.
+
+use serde_json::{json, Map, Value};
+
+/// Parse Modelfile text into the fields accepted by `/api/create`.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut spec = Map::new();
+    let mut parameters = Map::new();
+    let mut messages = Vec::new();
+    let mut licenses = Vec::new();
+
+    let mut lines = text.lines().enumerate();
+    while let Some((n, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (instruction, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let value = if let Some(body) = rest.strip_prefix("\"\"\"") {
+            read_block(body, &mut lines)
+                .ok_or_else(|| format!("line {}: unterminated \"\"\"", n + 1))?
+        } else {
+            unquote(rest).to_string()
+        };
+
+        match instruction.to_ascii_uppercase().as_str() {
+            "FROM" => {
+                spec.insert("from".into(), json!(value));
+            }
+            "SYSTEM" => {
+                spec.insert("system".into(), json!(value));
+            }
+            "TEMPLATE" => {
+                spec.insert("template".into(), json!(value));
+            }
+            "LICENSE" => licenses.push(json!(value)),
+            "PARAMETER" => {
+                let (name, raw) = value
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| format!("line {}: PARAMETER needs a name and value", n + 1))?;
+                let parsed = parameter_value(unquote(raw.trim()));
+                if name == "stop" {
+                    let stops = parameters.entry("stop").or_insert_with(|| json!([]));
+                    if let Some(list) = stops.as_array_mut() {
+                        list.push(parsed);
+                    }
+                } else {
+                    parameters.insert(name.to_string(), parsed);
+                }
+            }
+            "MESSAGE" => {
+                let (role, content) = value
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| format!("line {}: MESSAGE needs a role and content", n + 1))?;
+                messages.push(json!({"role": role, "content": unquote(content.trim())}));
+            }
+            "ADAPTER" => {
+                return Err(format!(
+                    "line {}: ADAPTER needs an uploaded blob; pass `adapters` in a structured spec",
+                    n + 1
+                ));
+            }
+            other => return Err(format!("line {}: unknown instruction '{other}'", n + 1)),
+        }
+    }
+
+    if !spec.contains_key("from") {
+        return Err("Modelfile requires a FROM instruction".to_string());
+    }
+    if !parameters.is_empty() {
+        spec.insert("parameters".into(), Value::Object(parameters));
+    }
+    if !messages.is_empty() {
+        spec.insert("messages".into(), json!(messages));
+    }
+    match licenses.len() {
+        0 => {}
+        1 => {
+            spec.insert("license".into(), licenses.remove(0));
+        }
+        _ => {
+            spec.insert("license".into(), json!(licenses));
+        }
+    }
+    Ok(Value::Object(spec))
+}
+
+/// Collect a `"""` block that may span several lines.
+fn read_block<'a>(
+    first: &str,
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+) -> Option<String> {
+    if let Some(end) = first.find("\"\"\"") {
+        return Some(first[..end].to_string());
+    }
+    let mut block = first.to_string();
+    for (_, line) in lines {
+        block.push('\n');
+        if let Some(end) = line.find("\"\"\"") {
+            block.push_str(&line[..end]);
+            return Some(block.trim_start_matches('\n').to_string());
+        }
+        block.push_str(line);
+    }
+    None
+}
+
+fn unquote(s: &str) -> &str {
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s)
+}
+
+fn parameter_value(raw: &str) -> Value {
+    if let Ok(n) = raw.parse::<i64>() {
+        return json!(n);
+    }
+    if let Ok(n) = raw.parse::<f64>() {
+        return json!(n);
+    }
+    match raw {
+        "true" => json!(true),
+        "false" => json!(false),
+        _ => json!(raw),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_instructions_into_a_create_spec() {
+        let text = r#"
+# comment
+FROM llama3.2
+SYSTEM """You are terse.
+Answer in one line."""
+PARAMETER temperature 0.2
+PARAMETER num_ctx 4096
+PARAMETER stop "<|end|>"
+PARAMETER stop "</s>"
+MESSAGE user "hi"
+MESSAGE assistant hello there
+LICENSE MIT
+"#;
+        let spec = parse(text).unwrap();
+        assert_eq!(
+            spec,
+            json!({
+                "from": "llama3.2",
+                "system": "You are terse.\nAnswer in one line.",
+                "parameters": {
+                    "temperature": 0.2,
+                    "num_ctx": 4096,
+                    "stop": ["<|end|>", "</s>"]
+                },
+                "messages": [
+                    {"role": "user", "content": "hi"},
+                    {"role": "assistant", "content": "hello there"}
+                ],
+                "license": "MIT"
+            })
+        );
+    }
+
+    #[test]
+    fn instructions_are_case_insensitive() {
+        let spec = parse("from base\nsystem \"be brief\"").unwrap();
+        assert_eq!(spec, json!({"from": "base", "system": "be brief"}));
+    }
+
+    #[test]
+    fn collects_several_licenses_into_a_list() {
+        let spec = parse("FROM base\nLICENSE MIT\nLICENSE Apache-2.0").unwrap();
+        assert_eq!(spec["license"], json!(["MIT", "Apache-2.0"]));
+    }
+
+    #[test]
+    fn requires_from() {
+        assert_eq!(
+            parse("SYSTEM hi").unwrap_err(),
+            "Modelfile requires a FROM instruction"
+        );
+    }
+
+    #[test]
+    fn reports_the_failing_line() {
+        assert_eq!(
+            parse("FROM base\nPARAMETER temperature").unwrap_err(),
+            "line 2: PARAMETER needs a name and value"
+        );
+        assert_eq!(
+            parse("FROM base\n\nQUANTIZE q4").unwrap_err(),
+            "line 3: unknown instruction 'QUANTIZE'"
+        );
+        assert_eq!(
+            parse("FROM base\nSYSTEM \"\"\"never closed").unwrap_err(),
+            "line 2: unterminated \"\"\""
+        );
+        assert!(parse("FROM base\nADAPTER ./lora.gguf")
+            .unwrap_err()
+            .starts_with("line 2: ADAPTER"));
+    }
+}
@@ -0,0 +1,181 @@
+//! Model aliases and per-capability default models.
+//!
+//! `model_aliases` in the config maps short names (`fast`, `smart`, ...) to
+//! model names; anywhere a model is named, an alias may be used instead.
+//! The aliases `code`, `embed` and `vision` double as the defaults for
+//! those capabilities when a request does not name a model.
+
+use serde_json::{json, Value};
+
+/// Used by `embeddings` when no `embed` alias is configured.
+pub const DEFAULT_EMBED_MODEL: &str = "nomic-embed-text";
+/// Guards against alias cycles.
+const MAX_HOPS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Chat,
+    Code,
+    Embed,
+    Vision,
+}
+
+impl Capability {
+    fn alias(self) -> Option<&'static str> {
+        match self {
+            Capability::Chat => None,
+            Capability::Code => Some("code"),
+            Capability::Embed => Some("embed"),
+            Capability::Vision => Some("vision"),
+        }
+    }
+}
+
+/// Follow `name` through the alias table to a model name.
+pub fn alias(config: &Value, name: &str) -> String {
+    let mut name = name;
+    for _ in 0..MAX_HOPS {
+        match config
+            .pointer(&format!(
+                "/model_aliases/{}",
+                name.replace('~', "~0").replace('/', "~1")
+            ))
+            .and_then(|v| v.as_str())
+        {
+            Some(target) if target != name => name = target,
+            _ => break,
+        }
+    }
+    name.to_string()
+}
+
+/// Model for a request: the one it names, else the capability's default,
+/// else `fallback` (the configured `model`), with aliases resolved.
+pub fn resolve(
+    config: &Value,
+    requested: Option<&str>,
+    capability: Capability,
+    fallback: &str,
+) -> String {
+    if let Some(name) = requested.filter(|n| !n.is_empty()) {
+        return alias(config, name);
+    }
+    let configured = capability
+        .alias()
+        .filter(|cap| config.pointer(&format!("/model_aliases/{cap}")).is_some())
+        .or_else(|| match capability {
+            // Kept from before aliases existed.
+            Capability::Code => config.get("code_model").and_then(|v| v.as_str()),
+            _ => None,
+        });
+    match (configured, capability) {
+        (Some(name), _) => alias(config, name),
+        (None, Capability::Embed) => DEFAULT_EMBED_MODEL.to_string(),
+        (None, _) => alias(config, fallback),
+    }
+}
+
+/// JSON schema for `model_aliases` in `config_schema()`.
+pub fn schema() -> Value {
+    json!({
+        "type": "object",
+        "description": "Short names for models, usable wherever a model is named. \
+                        code, embed and vision also set the default model for code \
+                        generation, embeddings and requests with images",
+        "additionalProperties": {"type": "string"},
+        "examples": [{
+            "fast": "llama3.2:1b",
+            "smart": "qwen2.5:32b",
+            "code": "qwen2.5-coder",
+            "embed": "nomic-embed-text",
+            "vision": "llava"
+        }]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_alias_chains() {
+        let config = json!({"model_aliases": {"smart": "big", "big": "qwen2.5:32b"}});
+        assert_eq!(alias(&config, "smart"), "qwen2.5:32b");
+        assert_eq!(alias(&config, "llama3.2"), "llama3.2");
+    }
+
+    #[test]
+    fn alias_cycles_terminate() {
+        let config = json!({"model_aliases": {"a": "b", "b": "a", "self": "self"}});
+        assert!(["a", "b"].contains(&alias(&config, "a").as_str()));
+        assert_eq!(alias(&config, "self"), "self");
+    }
+
+    #[test]
+    fn alias_names_may_contain_slashes() {
+        let config = json!({"model_aliases": {"team/chat": "llama3.2", "a~b": "phi3"}});
+        assert_eq!(alias(&config, "team/chat"), "llama3.2");
+        assert_eq!(alias(&config, "a~b"), "phi3");
+    }
+
+    #[test]
+    fn requested_model_wins() {
+        let config = json!({"model_aliases": {"code": "qwen2.5-coder", "fast": "llama3.2:1b"}});
+        let model = resolve(&config, Some("fast"), Capability::Code, "llama3.2");
+        assert_eq!(model, "llama3.2:1b");
+    }
+
+    #[test]
+    fn code_falls_back_from_alias_to_code_model_to_model() {
+        let both = json!({
+            "model_aliases": {"code": "qwen2.5-coder"},
+            "code_model": "codellama"
+        });
+        assert_eq!(
+            resolve(&both, None, Capability::Code, "llama3.2"),
+            "qwen2.5-coder"
+        );
+
+        let legacy = json!({"code_model": "coder", "model_aliases": {"coder": "codellama"}});
+        assert_eq!(
+            resolve(&legacy, None, Capability::Code, "llama3.2"),
+            "codellama"
+        );
+
+        assert_eq!(
+            resolve(&json!({}), None, Capability::Code, "llama3.2"),
+            "llama3.2"
+        );
+    }
+
+    #[test]
+    fn embed_defaults_to_nomic_instead_of_the_chat_model() {
+        assert_eq!(
+            resolve(&json!({}), None, Capability::Embed, "llama3.2"),
+            DEFAULT_EMBED_MODEL
+        );
+        let config = json!({"model_aliases": {"embed": "mxbai-embed-large"}});
+        assert_eq!(
+            resolve(&config, None, Capability::Embed, "llama3.2"),
+            "mxbai-embed-large"
+        );
+    }
+
+    #[test]
+    fn chat_and_vision_fall_back_to_the_configured_model() {
+        let config = json!({"model_aliases": {"default": "llama3.2:3b"}});
+        assert_eq!(
+            resolve(&config, None, Capability::Chat, "default"),
+            "llama3.2:3b"
+        );
+        assert_eq!(
+            resolve(&config, Some(""), Capability::Vision, "default"),
+            "llama3.2:3b"
+        );
+        let config = json!({"model_aliases": {"vision": "llava"}});
+        assert_eq!(
+            resolve(&config, None, Capability::Vision, "llama3.2"),
+            "llava"
+        );
+    }
+}
@@ -0,0 +1,517 @@
+//! Translation between OpenAI chat-completions / embeddings payloads and
+//! the plugin's native `chat` and `embeddings` inputs and results.
+
+use std::collections::HashMap;
+
+use serde_json::{json, Map, Value};
+
+use crate::error::OllamaError;
+
+/// Upper bound on `n`; each choice is a separate Ollama request.
+pub const MAX_CHOICES: u64 = 8;
+
+/// Native `chat` input for an OpenAI chat-completions request, plus the
+/// number of choices asked for.
+pub fn chat_input(request: &Value) -> Result<(Value, u64), OllamaError> {
+    if request.get("stream").and_then(|v| v.as_bool()) == Some(true) {
+        return Err(OllamaError::bad_request(
+            "stream is not supported for openai_chat; use chat with stream: true",
+        ));
+    }
+    let messages = request
+        .get("messages")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| OllamaError::bad_request("messages must be an array"))?;
+
+    let mut input = json!({"messages": translate_messages(messages)?});
+    if let Some(model) = request.get("model").and_then(|v| v.as_str()) {
+        input["model"] = json!(model);
+    }
+    // Plugin extension: pin the request to one configured endpoint.
+    if let Some(endpoint) = request.get("endpoint") {
+        input["endpoint"] = endpoint.clone();
+    }
+    if let Some(tools) = request.get("tools") {
+        input["tools"] = tools.clone();
+    }
+    if let Some(format) = response_format(request.get("response_format"))? {
+        input["format"] = format;
+    }
+
+    let mut options = Map::new();
+    for name in ["temperature", "top_p", "seed"] {
+        if let Some(value) = request.get(name).filter(|v| !v.is_null()) {
+            options.insert(name.to_string(), value.clone());
+        }
+    }
+    let max_tokens = request
+        .get("max_completion_tokens")
+        .or_else(|| request.get("max_tokens"))
+        .filter(|v| !v.is_null());
+    if let Some(max_tokens) = max_tokens {
+        options.insert("num_predict".to_string(), max_tokens.clone());
+    }
+    match request.get("stop") {
+        Some(Value::String(stop)) => {
+            options.insert("stop".to_string(), json!([stop]));
+        }
+        Some(stop @ Value::Array(_)) => {
+            options.insert("stop".to_string(), stop.clone());
+        }
+        _ => {}
+    }
+    if !options.is_empty() {
+        input["options"] = Value::Object(options);
+    }
+
+    let n = match request.get("n").filter(|v| !v.is_null()) {
+        None => 1,
+        Some(n) => n
+            .as_u64()
+            .filter(|n| (1..=MAX_CHOICES).contains(n))
+            .ok_or_else(|| {
+                OllamaError::bad_request(format!("n must be between 1 and {MAX_CHOICES}"))
+            })?,
+    };
+    if n > 1 {
+        // Identical requests would otherwise all be answered from the cache.
+        input["cache"] = json!(false);
+    } else if let Some(cache) = request.get("cache") {
+        input["cache"] = cache.clone();
+    }
+    Ok((input, n))
+}
+
+/// Flatten content parts, move `image_url` data URIs to `images`, parse
+/// tool-call arguments and name tool results after the call they answer.
+fn translate_messages(messages: &[Value]) -> Result<Vec<Value>, OllamaError> {
+    let mut tool_names: HashMap<String, String> = HashMap::new();
+    let mut out = Vec::with_capacity(messages.len());
+    for (i, msg) in messages.iter().enumerate() {
+        let role = msg.get("role").and_then(|v| v.as_str()).unwrap_or("");
+        let role = if role == "developer" { "system" } else { role };
+        let mut native = json!({"role": role, "content": ""});
+
+        match msg.get("content") {
+            Some(Value::String(text)) => native["content"] = json!(text),
+            Some(Value::Array(parts)) => {
+                let mut text = Vec::new();
+                let mut images = Vec::new();
+                for part in parts {
+                    match part.get("type").and_then(|v| v.as_str()) {
+                        Some("text") => text.push(part["text"].as_str().unwrap_or("")),
+                        Some("image_url") => {
+                            let url = part
+                                .pointer("/image_url/url")
+                                .or_else(|| part.get("image_url"))
+                                .and_then(|v| v.as_str())
+                                .unwrap_or("");
+                            if !url.starts_with("data:") {
+                                return Err(OllamaError::bad_request(format!(
+                                    "messages[{i}]: only data: URIs are supported for image_url"
+                                )));
+                            }
+                            images.push(json!(url));
+                        }
+                        other => {
+                            return Err(OllamaError::bad_request(format!(
+                                "messages[{i}]: unsupported content part {other:?}"
+                            )))
+                        }
+                    }
+                }
+                native["content"] = json!(text.join("\n"));
+                if !images.is_empty() {
+                    native["images"] = json!(images);
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(calls) = msg.get("tool_calls").and_then(|v| v.as_array()) {
+            let mut native_calls = Vec::with_capacity(calls.len());
+            for call in calls {
+                let name = call.pointer("/function/name").and_then(|v| v.as_str());
+                if let (Some(id), Some(name)) = (call.get("id").and_then(|v| v.as_str()), name) {
+                    tool_names.insert(id.to_string(), name.to_string());
+                }
+                let arguments = match call.pointer("/function/arguments") {
+                    Some(Value::String(raw)) => serde_json::from_str(raw).map_err(|e| {
+                        OllamaError::bad_request(format!(
+                            "messages[{i}]: tool call arguments are not valid JSON: {e}"
+                        ))
+                    })?,
+                    Some(args) => args.clone(),
+                    None => json!({}),
+                };
+                native_calls.push(json!({"function": {"name": name, "arguments": arguments}}));
+            }
+            native["tool_calls"] = json!(native_calls);
+        }
+        if let Some(name) = msg
+            .get("tool_call_id")
+            .and_then(|v| v.as_str())
+            .and_then(|id| tool_names.get(id))
+        {
+            native["tool_name"] = json!(name);
+        }
+        out.push(native);
+    }
+    Ok(out)
+}
+
+fn response_format(format: Option<&Value>) -> Result<Option<Value>, OllamaError> {
+    let Some(format) = format.filter(|v| !v.is_null()) else {
+        return Ok(None);
+    };
+    match format.get("type").and_then(|v| v.as_str()) {
+        Some("text") => Ok(None),
+        Some("json_object") => Ok(Some(json!("json"))),
+        Some("json_schema") => format
+            .pointer("/json_schema/schema")
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| {
+                OllamaError::bad_request("response_format.json_schema.schema is required")
+            }),
+        other => Err(OllamaError::bad_request(format!(
+            "unsupported response_format type {other:?}"
+        ))),
+    }
+}
+
+/// OpenAI chat-completion object built from one native `chat` result per
+/// choice.
+pub fn chat_response(results: &[Value], model: &str) -> Value {
+    let mut prompt_tokens = 0;
+    let mut completion_tokens = 0;
+    let choices: Vec<Value> = results
+        .iter()
+        .enumerate()
+        .map(|(index, result)| {
+            let tokens = |name: &str| {
+                result
+                    .pointer(&format!("/usage/{name}"))
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(0)
+            };
+            // The prompt is shared, so it is counted once.
+            prompt_tokens = prompt_tokens.max(tokens("prompt_tokens"));
+            completion_tokens += tokens("completion_tokens");
+            choice(index, result)
+        })
+        .collect();
+
+    let model = results
+        .first()
+        .and_then(|r| r["model"].as_str())
+        .unwrap_or(model);
+    json!({
+        "id": format!("chatcmpl-{}", unique_suffix()),
+        "object": "chat.completion",
+        "created": crate::store::now(),
+        "model": model,
+        "choices": choices,
+        "usage": {
+            "prompt_tokens": prompt_tokens,
+            "completion_tokens": completion_tokens,
+            "total_tokens": prompt_tokens + completion_tokens
+        }
+    })
+}
+
+fn choice(index: usize, result: &Value) -> Value {
+    let calls: Vec<Value> = result["tool_calls"]
+        .as_array()
+        .map(|calls| {
+            calls
+                .iter()
+                .enumerate()
+                .map(|(i, call)| {
+                    let arguments = match &call["function"]["arguments"] {
+                        Value::String(raw) => raw.clone(),
+                        args => args.to_string(),
+                    };
+                    json!({
+                        "id": format!("call_{index}_{i}"),
+                        "type": "function",
+                        "function": {"name": call["function"]["name"], "arguments": arguments}
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let finish_reason = if !calls.is_empty() {
+        "tool_calls"
+    } else if result["done_reason"].as_str() == Some("length") {
+        "length"
+    } else {
+        "stop"
+    };
+    let mut message = json!({
+        "role": "assistant",
+        "content": result["content"].as_str().filter(|c| !c.is_empty() || calls.is_empty())
+    });
+    if !calls.is_empty() {
+        message["tool_calls"] = json!(calls);
+    }
+    json!({"index": index, "message": message, "finish_reason": finish_reason})
+}
+
+/// Native `embeddings` input for an OpenAI embeddings request, plus whether
+/// vectors should be returned base64-encoded.
+pub fn embeddings_input(request: &Value) -> Result<(Value, bool), OllamaError> {
+    let texts = match request.get("input") {
+        Some(Value::String(text)) => vec![json!(text)],
+        Some(Value::Array(items)) if items.iter().all(|v| v.is_string()) => items.clone(),
+        _ => {
+            return Err(OllamaError::bad_request(
+                "input must be a string or an array of strings",
+            ))
+        }
+    };
+    let base64 = match request.get("encoding_format").and_then(|v| v.as_str()) {
+        None | Some("float") => false,
+        Some("base64") => true,
+        Some(other) => {
+            return Err(OllamaError::bad_request(format!(
+                "unsupported encoding_format '{other}'"
+            )))
+        }
+    };
+    let mut input = json!({"texts": texts});
+    for name in ["model", "dimensions", "endpoint", "cache"] {
+        if let Some(value) = request.get(name).filter(|v| !v.is_null()) {
+            input[name] = value.clone();
+        }
+    }
+    Ok((input, base64))
+}
+
+/// OpenAI embeddings list built from a native `embeddings` result.
+/// `encode` turns little-endian `f32` bytes into base64 when requested.
+pub fn embeddings_response(result: &Value, encode: Option<fn(&[u8]) -> String>) -> Value {
+    let data: Vec<Value> = result["embeddings"]
+        .as_array()
+        .map(|vectors| {
+            vectors
+                .iter()
+                .enumerate()
+                .map(|(index, vector)| {
+                    let embedding = match encode {
+                        Some(encode) => {
+                            let bytes: Vec<u8> = vector
+                                .as_array()
+                                .into_iter()
+                                .flatten()
+                                .filter_map(|v| v.as_f64())
+                                .flat_map(|v| (v as f32).to_le_bytes())
+                                .collect();
+                            json!(encode(&bytes))
+                        }
+                        None => vector.clone(),
+                    };
+                    json!({"object": "embedding", "index": index, "embedding": embedding})
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let prompt_tokens = result
+        .pointer("/usage/prompt_tokens")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0);
+    json!({
+        "object": "list",
+        "data": data,
+        "model": result["model"],
+        "usage": {"prompt_tokens": prompt_tokens, "total_tokens": prompt_tokens}
+    })
+}
+
+fn unique_suffix() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!("{nanos:x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat(request: Value) -> Value {
+        chat_input(&request).unwrap().0
+    }
+
+    #[test]
+    fn maps_developer_role_and_image_parts() {
+        let input = chat(json!({
+            "messages": [
+                {"role": "developer", "content": "Be brief."},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "What is this?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBO"}}
+                ]}
+            ]
+        }));
+        assert_eq!(
+            input["messages"],
+            json!([
+                {"role": "system", "content": "Be brief."},
+                {
+                    "role": "user",
+                    "content": "What is this?",
+                    "images": ["data:image/png;base64,iVBO"]
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_remote_image_urls() {
+        let request = json!({"messages": [{"role": "user", "content": [
+            {"type": "image_url", "image_url": {"url": "https://example.com/cat.png"}}
+        ]}]});
+        let err = chat_input(&request).unwrap_err();
+        assert!(err.message.contains("only data: URIs"), "{}", err.message);
+    }
+
+    #[test]
+    fn names_tool_results_after_their_call() {
+        let input = chat(json!({
+            "messages": [
+                {"role": "user", "content": "Weather in Paris?"},
+                {"role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_abc",
+                    "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}
+                }]},
+                {"role": "tool", "tool_call_id": "call_abc", "content": "18C"}
+            ]
+        }));
+        let messages = input["messages"].as_array().unwrap();
+        assert_eq!(
+            messages[1]["tool_calls"],
+            json!([{"function": {"name": "get_weather", "arguments": {"city": "Paris"}}}])
+        );
+        assert_eq!(messages[2]["tool_name"], "get_weather");
+        assert_eq!(messages[2]["content"], "18C");
+    }
+
+    #[test]
+    fn maps_response_format() {
+        let format = |value: Value| {
+            chat(json!({"messages": [], "response_format": value}))
+                .get("format")
+                .cloned()
+        };
+        assert_eq!(format(json!({"type": "text"})), None);
+        assert_eq!(format(json!({"type": "json_object"})), Some(json!("json")));
+        let schema = json!({"type": "object"});
+        assert_eq!(
+            format(json!({"type": "json_schema", "json_schema": {"schema": schema}})),
+            Some(schema)
+        );
+        let request = json!({"messages": [], "response_format": {"type": "json_schema"}});
+        assert!(chat_input(&request).is_err());
+    }
+
+    #[test]
+    fn maps_sampling_options() {
+        let input = chat(json!({
+            "messages": [],
+            "temperature": 0.1,
+            "max_tokens": 50,
+            "stop": "END",
+            "top_p": null
+        }));
+        assert_eq!(
+            input["options"],
+            json!({"temperature": 0.1, "num_predict": 50, "stop": ["END"]})
+        );
+    }
+
+    #[test]
+    fn several_choices_bypass_the_cache() {
+        let (input, n) = chat_input(&json!({"messages": [], "n": 3, "cache": true})).unwrap();
+        assert_eq!(n, 3);
+        assert_eq!(input["cache"], false);
+
+        let (input, n) = chat_input(&json!({"messages": [], "cache": true})).unwrap();
+        assert_eq!(n, 1);
+        assert_eq!(input["cache"], true);
+
+        assert!(chat_input(&json!({"messages": [], "n": MAX_CHOICES + 1})).is_err());
+        assert!(chat_input(&json!({"messages": [], "stream": true})).is_err());
+    }
+
+    #[test]
+    fn reports_finish_reasons_and_shared_prompt_usage() {
+        let usage = |completion: u64| json!({"prompt_tokens": 7, "completion_tokens": completion});
+        let results = [
+            json!({"content": "Hi.", "done_reason": "stop", "usage": usage(2), "model": "m"}),
+            json!({"content": "Once upon", "done_reason": "length", "usage": usage(3)}),
+            json!({
+                "content": "",
+                "tool_calls": [{"function": {"name": "lookup", "arguments": {"q": "x"}}}],
+                "usage": usage(4)
+            }),
+        ];
+        let response = chat_response(&results, "fallback");
+        assert_eq!(response["model"], "m");
+        let reasons: Vec<&str> = response["choices"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["finish_reason"].as_str().unwrap())
+            .collect();
+        assert_eq!(reasons, vec!["stop", "length", "tool_calls"]);
+
+        let message = &response["choices"][2]["message"];
+        assert!(message["content"].is_null());
+        assert_eq!(message["tool_calls"][0]["id"], "call_2_0");
+        assert_eq!(
+            message["tool_calls"][0]["function"]["arguments"],
+            "{\"q\":\"x\"}"
+        );
+        assert_eq!(
+            response["usage"],
+            json!({"prompt_tokens": 7, "completion_tokens": 9, "total_tokens": 16})
+        );
+    }
+
+    #[test]
+    fn encodes_embeddings_as_base64_on_request() {
+        let (input, base64) = embeddings_input(&json!({
+            "input": "hello",
+            "encoding_format": "base64",
+            "model": "nomic-embed-text"
+        }))
+        .unwrap();
+        assert!(base64);
+        assert_eq!(
+            input,
+            json!({"texts": ["hello"], "model": "nomic-embed-text"})
+        );
+
+        let result = json!({
+            "embeddings": [[1.0, -2.0]],
+            "model": "nomic-embed-text",
+            "usage": {"prompt_tokens": 2}
+        });
+        let floats = embeddings_response(&result, None);
+        assert_eq!(floats["data"][0]["embedding"], json!([1.0, -2.0]));
+        let encoded = embeddings_response(&result, Some(crate::base64_encode));
+        // 1.0f32 and -2.0f32 as little-endian bytes.
+        assert_eq!(encoded["data"][0]["embedding"], "AACAPwAAAMA=");
+        assert_eq!(
+            encoded["usage"],
+            json!({"prompt_tokens": 2, "total_tokens": 2})
+        );
+
+        let err = embeddings_input(&json!({"input": [1, 2]})).unwrap_err();
+        assert!(err.message.contains("input must be"));
+    }
+}
@@ -0,0 +1,86 @@
+//! Ollama model options (`temperature`, `num_ctx`, `stop`, ...).
+//!
+//! Per-request `options` are merged over the `options` object in the plugin
+//! config, and every key is type-checked before it is sent to Ollama.
+
+use serde_json::{json, Map, Value};
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Number,
+    Integer,
+    StringList,
+}
+
+const KNOWN: &[(&str, Kind, &str)] = &[
+    ("temperature", Kind::Number, "Sampling temperature"),
+    ("top_p", Kind::Number, "Nucleus sampling probability mass"),
+    ("top_k", Kind::Integer, "Sample from the k most likely tokens"),
+    ("num_ctx", Kind::Integer, "Context window size in tokens"),
+    ("num_predict", Kind::Integer, "Maximum tokens to generate"),
+    ("repeat_penalty", Kind::Number, "Penalty for repeated tokens"),
+    ("seed", Kind::Integer, "Random seed for reproducible output"),
+    ("stop", Kind::StringList, "Stop sequences"),
+];
+
+/// JSON schema for the `options` object in `config_schema()`.
+pub fn schema() -> Value {
+    let mut properties = Map::new();
+    for (name, kind, description) in KNOWN {
+        let prop = match kind {
+            Kind::Number => json!({"type": "number", "description": description}),
+            Kind::Integer => json!({"type": "integer", "description": description}),
+            Kind::StringList => json!({
+                "type": "array",
+                "items": {"type": "string"},
+                "description": description
+            }),
+        };
+        properties.insert(name.to_string(), prop);
+    }
+    json!({
+        "type": "object",
+        "description": "Default Ollama model options, overridable per request",
+        "properties": properties,
+        "additionalProperties": false
+    })
+}
+
+/// Merge request options over config defaults, returning `None` when
+/// neither sets anything.
+pub fn resolve(config: &Value, request: Option<&Value>) -> Result<Option<Value>, String> {
+    let mut merged = Map::new();
+    for (source, options) in [("config", config.get("options")), ("request", request)] {
+        let Some(options) = options.filter(|v| !v.is_null()) else {
+            continue;
+        };
+        let options = options
+            .as_object()
+            .ok_or_else(|| format!("{source} options must be an object"))?;
+        for (key, value) in options {
+            merged.insert(key.clone(), check(key, value)?);
+        }
+    }
+    Ok((!merged.is_empty()).then_some(Value::Object(merged)))
+}
+
+fn check(key: &str, value: &Value) -> Result<Value, String> {
+    let Some((_, kind, _)) = KNOWN.iter().find(|(name, _, _)| *name == key) else {
+        let known: Vec<&str> = KNOWN.iter().map(|(name, _, _)| *name).collect();
+        return Err(format!(
+            "unknown option '{key}' (expected one of: {})",
+            known.join(", ")
+        ));
+    };
+    match kind {
+        Kind::Number if value.is_number() => Ok(value.clone()),
+        Kind::Integer if value.is_i64() || value.is_u64() => Ok(value.clone()),
+        Kind::StringList => match value {
+            Value::String(s) => Ok(json!([s])),
+            Value::Array(list) if list.iter().all(|v| v.is_string()) => Ok(value.clone()),
+            _ => Err(format!("option '{key}' must be a string or array of strings")),
+        },
+        Kind::Number => Err(format!("option '{key}' must be a number")),
+        Kind::Integer => Err(format!("option '{key}' must be an integer")),
+    }
+}
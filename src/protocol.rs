@@ -0,0 +1,128 @@
+//! Versioned request envelope for messages from peer agents.
+//!
+//! A request looks like
+//! `{"version": 1, "request_id": "...", "action": "chat", "params": {...}}`
+//! and is answered with the same `request_id` and the action's full result.
+//! Payloads carrying only a bare `prompt` are still accepted as legacy
+//! chat requests.
+
+use serde_json::{json, Value};
+
+pub const VERSION: u64 = 1;
+
+/// Actions peers may request unless `agent_actions` is configured.
+const DEFAULT_AGENT_ACTIONS: &[&str] = &[
+    "chat",
+    "generate",
+    "embeddings",
+    "code",
+    "complete",
+    "list_models",
+    "show_model",
+    "running_models",
+];
+
+pub struct AgentRequest {
+    pub request_id: Option<String>,
+    pub action: String,
+    pub params: Value,
+    /// A bare `{"prompt": ...}` payload from before the envelope existed.
+    pub legacy: bool,
+}
+
+impl AgentRequest {
+    pub fn parse(payload: &Value) -> Result<Self, String> {
+        if payload.get("action").is_none() && payload.get("version").is_none() {
+            let prompt = payload.get("prompt").and_then(|v| v.as_str()).unwrap_or("");
+            if prompt.is_empty() {
+                return Err("payload has neither an action nor a prompt".to_string());
+            }
+            return Ok(Self {
+                request_id: None,
+                action: "chat".to_string(),
+                params: payload.clone(),
+                legacy: true,
+            });
+        }
+
+        let version = payload
+            .get("version")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(VERSION);
+        if version != VERSION {
+            return Err(format!("unsupported protocol version: {version}"));
+        }
+        let action = payload
+            .get("action")
+            .and_then(|v| v.as_str())
+            .filter(|a| !a.is_empty())
+            .ok_or_else(|| "action is required".to_string())?;
+        let params = match payload.get("params") {
+            None | Some(Value::Null) => json!({}),
+            Some(params @ Value::Object(_)) => params.clone(),
+            Some(_) => return Err("params must be an object".to_string()),
+        };
+
+        Ok(Self {
+            request_id: payload
+                .get("request_id")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            action: action.to_string(),
+            params,
+            legacy: false,
+        })
+    }
+
+    /// The action's input as `process` would receive it.
+    pub fn input(&self) -> Value {
+        let mut input = self.params.clone();
+        input["action"] = json!(self.action);
+        input
+    }
+
+    pub fn streams(&self) -> bool {
+        self.params
+            .get("stream")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    }
+
+    /// Reply carrying the action's full result.
+    pub fn reply(&self, result: Value) -> Value {
+        json!({
+            "version": VERSION,
+            "request_id": self.request_id,
+            "action": self.action,
+            "ok": true,
+            "result": result
+        })
+    }
+}
+
+/// Error reply for `payload`, echoing its `request_id` and `action` when
+/// it used the envelope so the sender can correlate it.
+pub fn error_reply(payload: &Value, error: Value) -> Value {
+    if payload.get("action").is_none() && payload.get("version").is_none() {
+        return json!({"error": error});
+    }
+    json!({
+        "version": VERSION,
+        "request_id": payload.get("request_id"),
+        "action": payload.get("action"),
+        "ok": false,
+        "error": error
+    })
+}
+
+/// Whether peers may request `action` under the current config.
+pub fn allowed(config: &Value, action: &str) -> bool {
+    match config.get("agent_actions").and_then(|v| v.as_array()) {
+        Some(list) => list.iter().any(|v| v.as_str() == Some(action)),
+        None => DEFAULT_AGENT_ACTIONS.contains(&action),
+    }
+}
+
+pub fn default_actions() -> &'static [&'static str] {
+    DEFAULT_AGENT_ACTIONS
+}
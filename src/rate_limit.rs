@@ -0,0 +1,146 @@
+//! Per-sender admission control for agent requests.
+//!
+//! Limits come from the `rate_limits` config: `requests_per_minute` and
+//! `tokens_per_day` apply to every sender unless overridden under
+//! `per_agent`, and `allow` / `deny` lists gate senders outright. Counters
+//! use fixed windows and live in the persistent variable store.
+
+use serde_json::{json, Value};
+
+use crate::error::{Category, OllamaError};
+use crate::store;
+
+const MINUTE: u64 = 60;
+const DAY: u64 = 24 * 60 * 60;
+
+fn key(sender: &str) -> String {
+    format!("ratelimit:{sender}")
+}
+
+fn limit(rules: &Value, sender: &str, name: &str) -> Option<u64> {
+    rules
+        .pointer(&format!("/per_agent/{}/{name}", escape_pointer(sender)))
+        .or_else(|| rules.get(name))
+        .and_then(|v| v.as_u64())
+}
+
+fn escape_pointer(segment: &str) -> String {
+    segment.replace('~', "~0").replace('/', "~1")
+}
+
+fn listed(list: Option<&Value>, sender: &str) -> Option<bool> {
+    list.and_then(|v| v.as_array())
+        .map(|names| names.iter().any(|n| n.as_str() == Some(sender)))
+}
+
+/// Load counters for `sender`, rolling over any elapsed windows.
+fn counters(sender: &str, now: u64) -> Value {
+    let mut state = store::load(&key(sender)).unwrap_or_else(
+        || json!({"minute_start": now, "minute_requests": 0, "day_start": now, "day_tokens": 0}),
+    );
+    if now >= state["minute_start"].as_u64().unwrap_or(0) + MINUTE {
+        state["minute_start"] = json!(now);
+        state["minute_requests"] = json!(0);
+    }
+    if now >= state["day_start"].as_u64().unwrap_or(0) + DAY {
+        state["day_start"] = json!(now);
+        state["day_tokens"] = json!(0);
+    }
+    state
+}
+
+fn rate_limited(message: String, retry_after: u64) -> OllamaError {
+    OllamaError::new(Category::RateLimited, message)
+        .with_details(json!({"retry_after": retry_after.max(1)}))
+}
+
+/// Admit one request from `sender`, counting it against the minute window.
+pub fn admit(config: &Value, sender: &str) -> Result<(), OllamaError> {
+    let rules = config.get("rate_limits").cloned().unwrap_or(json!({}));
+    if listed(rules.get("deny"), sender) == Some(true)
+        || listed(rules.get("allow"), sender) == Some(false)
+    {
+        return Err(OllamaError::new(
+            Category::Forbidden,
+            format!("agent '{sender}' is not allowed to use this agent"),
+        ));
+    }
+
+    let rpm = limit(&rules, sender, "requests_per_minute");
+    let tokens_per_day = limit(&rules, sender, "tokens_per_day");
+    if rpm.is_none() && tokens_per_day.is_none() {
+        return Ok(());
+    }
+
+    let now = store::now();
+    let mut state = counters(sender, now);
+
+    if let Some(max) = tokens_per_day {
+        let used = state["day_tokens"].as_u64().unwrap_or(0);
+        if used >= max {
+            let reset = state["day_start"].as_u64().unwrap_or(now) + DAY;
+            return Err(rate_limited(
+                format!("daily token quota of {max} reached; please try again later"),
+                reset.saturating_sub(now),
+            ));
+        }
+    }
+    let requests = state["minute_requests"].as_u64().unwrap_or(0);
+    if let Some(max) = rpm {
+        if requests >= max {
+            let reset = state["minute_start"].as_u64().unwrap_or(now) + MINUTE;
+            return Err(rate_limited(
+                format!("limit of {max} requests per minute reached; please slow down"),
+                reset.saturating_sub(now),
+            ));
+        }
+    }
+
+    state["minute_requests"] = json!(requests + 1);
+    store::save(&key(sender), &state)
+}
+
+/// Charge `tokens` (prompt plus generated) to `sender`'s daily quota.
+pub fn charge(sender: &str, tokens: u64) -> Result<(), OllamaError> {
+    if tokens == 0 {
+        return Ok(());
+    }
+    let now = store::now();
+    let mut state = counters(sender, now);
+    state["day_tokens"] = json!(state["day_tokens"].as_u64().unwrap_or(0) + tokens);
+    store::save(&key(sender), &state)
+}
+
+/// JSON schema for the `rate_limits` object in `config_schema()`.
+pub fn schema() -> Value {
+    let limits = json!({
+        "requests_per_minute": {"type": "integer", "description": "Requests per sender per minute"},
+        "tokens_per_day": {
+            "type": "integer",
+            "description": "Prompt plus generated tokens per sender per day"
+        }
+    });
+    json!({
+        "type": "object",
+        "description": "Limits on inference driven by peer agents",
+        "properties": {
+            "requests_per_minute": limits["requests_per_minute"],
+            "tokens_per_day": limits["tokens_per_day"],
+            "allow": {
+                "type": "array",
+                "items": {"type": "string"},
+                "description": "If set, only these agents may send requests"
+            },
+            "deny": {
+                "type": "array",
+                "items": {"type": "string"},
+                "description": "Agents whose requests are always refused"
+            },
+            "per_agent": {
+                "type": "object",
+                "description": "Overrides keyed by agent name",
+                "additionalProperties": {"type": "object", "properties": limits}
+            }
+        }
+    })
+}
@@ -0,0 +1,138 @@
+//! Separating model reasoning ("thinking") from the answer.
+//!
+//! Ollama splits reasoning into a `thinking` field when `think` is set and
+//! the model supports it; otherwise reasoning models such as deepseek-r1
+//! emit it inline between `<think>` tags.
+
+use serde_json::Value;
+
+const OPEN: &str = "<think>";
+const CLOSE: &str = "</think>";
+
+/// Check a `think` request value: a boolean or a reasoning effort level.
+pub fn check(think: &Value) -> Result<(), String> {
+    match think {
+        Value::Bool(_) => Ok(()),
+        Value::String(level) if matches!(level.as_str(), "low" | "medium" | "high") => Ok(()),
+        _ => Err("think must be a boolean or one of \"low\", \"medium\", \"high\"".to_string()),
+    }
+}
+
+/// Pull inline `<think>` sections out of `text`, returning
+/// `(thinking, content)`. An unclosed tag is treated as all reasoning.
+pub fn split_inline(text: &str) -> (String, String) {
+    let mut thinking = Vec::new();
+    let mut content = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find(OPEN) {
+        content.push_str(&rest[..start]);
+        let after = &rest[start + OPEN.len()..];
+        match after.find(CLOSE) {
+            Some(end) => {
+                thinking.push(after[..end].trim());
+                rest = &after[end + CLOSE.len()..];
+            }
+            None => {
+                thinking.push(after.trim());
+                rest = "";
+            }
+        }
+    }
+    // Some templates open the tag in the prompt, so only the close appears.
+    if thinking.is_empty() {
+        if let Some(end) = rest.find(CLOSE) {
+            thinking.push(rest[..end].trim());
+            rest = &rest[end + CLOSE.len()..];
+        }
+    }
+    content.push_str(rest);
+
+    (thinking.join("\n\n"), content.trim().to_string())
+}
+
+/// Strips inline `<think>` sections from a sequence of streamed deltas.
+#[derive(Default)]
+pub struct StreamFilter {
+    inside: bool,
+}
+
+impl StreamFilter {
+    /// Return the visible part of `delta`, tracking tags across calls.
+    pub fn visible(&mut self, delta: &str) -> String {
+        let mut out = String::new();
+        let mut rest = delta;
+        loop {
+            let tag = if self.inside { CLOSE } else { OPEN };
+            match rest.find(tag) {
+                Some(pos) => {
+                    if !self.inside {
+                        out.push_str(&rest[..pos]);
+                    }
+                    rest = &rest[pos + tag.len()..];
+                    self.inside = !self.inside;
+                }
+                None => {
+                    if !self.inside {
+                        out.push_str(rest);
+                    }
+                    return out;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separates_think_sections_from_the_answer() {
+        assert_eq!(
+            split_inline("<think>\n2 + 2 is 4\n</think>\n\nThe answer is 4."),
+            ("2 + 2 is 4".to_string(), "The answer is 4.".to_string())
+        );
+    }
+
+    #[test]
+    fn joins_several_sections() {
+        let (thinking, content) = split_inline("<think>a</think>one <think>b</think>two");
+        assert_eq!(thinking, "a\n\nb");
+        assert_eq!(content, "one two");
+    }
+
+    #[test]
+    fn unclosed_tag_is_all_reasoning() {
+        assert_eq!(
+            split_inline("Sure. <think>still going"),
+            ("still going".to_string(), "Sure.".to_string())
+        );
+    }
+
+    #[test]
+    fn handles_a_close_tag_opened_by_the_template() {
+        assert_eq!(
+            split_inline("weighing options</think>Pick B."),
+            ("weighing options".to_string(), "Pick B.".to_string())
+        );
+    }
+
+    #[test]
+    fn plain_text_has_no_thinking() {
+        assert_eq!(
+            split_inline("  hello  "),
+            (String::new(), "hello".to_string())
+        );
+    }
+
+    #[test]
+    fn stream_filter_tracks_tags_across_deltas() {
+        let mut filter = StreamFilter::default();
+        let visible: String = ["a<think>x", "y</think>b"]
+            .iter()
+            .map(|delta| filter.visible(delta))
+            .collect();
+        assert_eq!(visible, "ab");
+    }
+}
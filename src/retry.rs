@@ -0,0 +1,163 @@
+//! Retry and timeout policy for the shared Ollama HTTP path.
+//!
+//! Requests run synchronously on the host, so an in-flight call cannot be
+//! cut short; the per-action timeout bounds the total time spent across
+//! attempts and stops further retries once it would be exceeded.
+
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::error::{Category, OllamaError};
+
+/// Actions whose requests are safe to repeat.
+const RETRYABLE_ACTIONS: &[&str] = &["chat", "generate", "embeddings", "list_models"];
+
+const DEFAULT_MAX_ATTEMPTS: u64 = 3;
+const DEFAULT_BACKOFF_BASE_MS: u64 = 250;
+const DEFAULT_BACKOFF_CAP_MS: u64 = 8_000;
+const DEFAULT_RETRY_ON: &[Category] = &[Category::Unreachable, Category::ServerError];
+
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff_base: Duration,
+    backoff_cap: Duration,
+    retry_on: Vec<Category>,
+    timeout: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// Build the policy for `action` from the `retry` and `timeouts` config.
+    pub fn for_action(config: &Value, action: &str) -> Self {
+        let retry = config.get("retry");
+        let setting = |key: &str, default: u64| {
+            retry
+                .and_then(|r| r.get(key))
+                .and_then(|v| v.as_u64())
+                .unwrap_or(default)
+        };
+
+        let max_attempts = if RETRYABLE_ACTIONS.contains(&action) {
+            setting("max_attempts", DEFAULT_MAX_ATTEMPTS).clamp(1, 10) as u32
+        } else {
+            1
+        };
+        let retry_on = match retry
+            .and_then(|r| r.get("retry_on"))
+            .and_then(|v| v.as_array())
+        {
+            Some(list) => list
+                .iter()
+                .filter_map(|v| v.as_str().and_then(Category::parse))
+                .collect(),
+            None => DEFAULT_RETRY_ON.to_vec(),
+        };
+        let timeouts = config.get("timeouts");
+        let timeout = timeouts
+            .and_then(|t| t.get(action).or_else(|| t.get("default")))
+            .and_then(|v| v.as_u64())
+            .map(Duration::from_millis);
+
+        let backoff_base = setting("backoff_base_ms", DEFAULT_BACKOFF_BASE_MS);
+        let backoff_cap = setting("backoff_cap_ms", DEFAULT_BACKOFF_CAP_MS);
+
+        Self {
+            max_attempts,
+            backoff_base: Duration::from_millis(backoff_base),
+            backoff_cap: Duration::from_millis(backoff_cap),
+            retry_on,
+            timeout,
+        }
+    }
+
+    /// Delay before the attempt following `attempt` (1-based).
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32 << (attempt - 1).min(16);
+        self.backoff_base
+            .saturating_mul(factor)
+            .min(self.backoff_cap)
+    }
+
+    /// Run `call` until it succeeds, fails with a non-retryable category,
+    /// or runs out of attempts or time. Returns the attempt count as well.
+    pub fn run<T>(
+        &self,
+        mut call: impl FnMut() -> Result<T, OllamaError>,
+    ) -> (Result<T, OllamaError>, u32) {
+        let started = Instant::now();
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let err = match call() {
+                Ok(value) => return (Ok(value), attempt),
+                Err(e) => e,
+            };
+            if attempt >= self.max_attempts || !self.retry_on.contains(&err.category) {
+                return (Err(err), attempt);
+            }
+            let delay = self.backoff(attempt);
+            if let Some(timeout) = self.timeout {
+                if started.elapsed() + delay >= timeout {
+                    let message = format!(
+                        "{} (gave up after {} ms, timeout {} ms)",
+                        err.message,
+                        started.elapsed().as_millis(),
+                        timeout.as_millis()
+                    );
+                    return (Err(OllamaError { message, ..err }), attempt);
+                }
+            }
+            std::thread::sleep(delay);
+        }
+    }
+}
+
+/// JSON schema for the `retry` and `timeouts` objects in `config_schema()`.
+pub fn schema() -> (Value, Value) {
+    let categories: Vec<&str> = [
+        Category::ModelNotFound,
+        Category::BadRequest,
+        Category::ServerError,
+        Category::Unreachable,
+        Category::InvalidResponse,
+    ]
+    .iter()
+    .map(|c| c.as_str())
+    .collect();
+    let defaults: Vec<&str> = DEFAULT_RETRY_ON.iter().map(|c| c.as_str()).collect();
+
+    let retry = json!({
+        "type": "object",
+        "description": "Retry policy for chat, generate, embeddings and list_models",
+        "properties": {
+            "max_attempts": {
+                "type": "integer",
+                "description": "Total attempts including the first",
+                "default": DEFAULT_MAX_ATTEMPTS
+            },
+            "backoff_base_ms": {
+                "type": "integer",
+                "description": "Delay before the first retry; doubles on each retry",
+                "default": DEFAULT_BACKOFF_BASE_MS
+            },
+            "backoff_cap_ms": {
+                "type": "integer",
+                "description": "Upper bound on the delay between attempts",
+                "default": DEFAULT_BACKOFF_CAP_MS
+            },
+            "retry_on": {
+                "type": "array",
+                "items": {"type": "string", "enum": categories},
+                "description": "Error categories that trigger a retry",
+                "default": defaults
+            }
+        }
+    });
+    let timeouts = json!({
+        "type": "object",
+        "description": "Per-action time budget in milliseconds across all attempts; \
+                        `default` applies to actions not listed",
+        "additionalProperties": {"type": "integer"}
+    });
+    (retry, timeouts)
+}
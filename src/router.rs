@@ -0,0 +1,480 @@
+//! Endpoint selection across several Ollama hosts.
+//!
+//! Endpoints come from the `endpoints` config (falling back to the single
+//! `ollama_url`). Each request gets an ordered list of candidates: hosts
+//! whose circuit is open are skipped, hosts known to carry the model come
+//! first, and the `routing.strategy` decides the rest. Model inventories
+//! from `/api/tags` and health counters are kept in plugin storage.
+
+use serde_json::{json, Value};
+
+use crate::error::OllamaError;
+use crate::store;
+
+const HEALTH_KEY: &str = "router:health";
+const ROUND_ROBIN_KEY: &str = "router:round_robin";
+pub const DEFAULT_FAILURE_THRESHOLD: u64 = 3;
+pub const DEFAULT_COOLDOWN_SECONDS: u64 = 30;
+pub const DEFAULT_INVENTORY_TTL_SECONDS: u64 = 300;
+
+/// Fetches a JSON document from a path on an endpoint, or `None` when the
+/// host fails.
+pub type Probe<'a> = &'a dyn Fn(&Endpoint, &str) -> Option<Value>;
+
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    pub name: String,
+    pub url: String,
+    pub weight: u64,
+    /// This host's own `auth`, used instead of the global settings.
+    pub auth: Option<Value>,
+    /// Static model inventory; discovered from `/api/tags` when absent.
+    models: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    RoundRobin,
+    LeastLoaded,
+    ModelAffinity,
+}
+
+impl Strategy {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "round_robin" => Some(Strategy::RoundRobin),
+            "least_loaded" => Some(Strategy::LeastLoaded),
+            "model_affinity" => Some(Strategy::ModelAffinity),
+            _ => None,
+        }
+    }
+}
+
+struct Settings {
+    strategy: Strategy,
+    failure_threshold: u64,
+    cooldown: u64,
+    inventory_ttl: u64,
+}
+
+impl Settings {
+    fn resolve(config: &Value) -> Result<Self, OllamaError> {
+        let routing = config.get("routing");
+        let int = |name: &str, default: u64| {
+            routing
+                .and_then(|r| r.get(name))
+                .and_then(|v| v.as_u64())
+                .unwrap_or(default)
+        };
+        let strategy = match routing
+            .and_then(|r| r.get("strategy"))
+            .and_then(|v| v.as_str())
+        {
+            None => Strategy::RoundRobin,
+            Some(name) => Strategy::parse(name).ok_or_else(|| {
+                OllamaError::bad_request(format!(
+                    "unknown routing strategy '{name}' \
+                     (expected round_robin, least_loaded or model_affinity)"
+                ))
+            })?,
+        };
+        Ok(Self {
+            strategy,
+            failure_threshold: int("failure_threshold", DEFAULT_FAILURE_THRESHOLD).max(1),
+            cooldown: int("cooldown_seconds", DEFAULT_COOLDOWN_SECONDS),
+            inventory_ttl: int("inventory_ttl_seconds", DEFAULT_INVENTORY_TTL_SECONDS),
+        })
+    }
+}
+
+/// Every configured endpoint, or one built from `default_url`.
+pub fn configured(config: &Value, default_url: &str) -> Result<Vec<Endpoint>, OllamaError> {
+    let Some(list) = config
+        .get("endpoints")
+        .and_then(|v| v.as_array())
+        .filter(|l| !l.is_empty())
+    else {
+        return Ok(vec![Endpoint {
+            name: "default".to_string(),
+            url: default_url.to_string(),
+            weight: 1,
+            auth: None,
+            models: None,
+        }]);
+    };
+
+    list.iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let url = entry
+                .as_str()
+                .or_else(|| entry.get("url").and_then(|v| v.as_str()))
+                .ok_or_else(|| {
+                    OllamaError::bad_request(format!("endpoints[{i}]: url is required"))
+                })?
+                .trim_end_matches('/')
+                .to_string();
+            let models = entry
+                .get("models")
+                .and_then(|v| v.as_array())
+                .map(|m| m.iter().filter_map(|v| v.as_str()).map(normalize).collect());
+            Ok(Endpoint {
+                name: entry
+                    .get("name")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or(&url)
+                    .to_string(),
+                weight: entry
+                    .get("weight")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(1)
+                    .max(1),
+                auth: entry.get("auth").cloned(),
+                url,
+                models,
+            })
+        })
+        .collect()
+}
+
+/// The endpoint called `name`, for requests pinned to one host.
+pub fn find(config: &Value, default_url: &str, name: &str) -> Result<Endpoint, OllamaError> {
+    configured(config, default_url)?
+        .into_iter()
+        .find(|e| e.name == name)
+        .ok_or_else(|| OllamaError::bad_request(format!("unknown endpoint '{name}'")))
+}
+
+/// Every endpoint for host-level requests such as `/api/tags`, in config
+/// order with open circuits moved to the back.
+pub fn hosts(config: &Value, default_url: &str) -> Result<Vec<Endpoint>, OllamaError> {
+    let endpoints = configured(config, default_url)?;
+    if endpoints.len() == 1 {
+        return Ok(endpoints);
+    }
+    let settings = Settings::resolve(config)?;
+    let health = store::load(HEALTH_KEY).unwrap_or_else(|| json!({}));
+    let now = store::now();
+    let (mut closed, open): (Vec<_>, Vec<_>) = endpoints
+        .into_iter()
+        .partition(|e| !circuit_open(&health[&e.name], &settings, now));
+    closed.extend(open);
+    Ok(closed)
+}
+
+/// Candidates for serving `model`, best first.
+pub fn plan(
+    config: &Value,
+    default_url: &str,
+    model: &str,
+    probe: Probe,
+) -> Result<Vec<Endpoint>, OllamaError> {
+    let endpoints = configured(config, default_url)?;
+    if endpoints.len() == 1 {
+        return Ok(endpoints);
+    }
+    let settings = Settings::resolve(config)?;
+    let health = store::load(HEALTH_KEY).unwrap_or_else(|| json!({}));
+    let now = store::now();
+
+    let (mut closed, open): (Vec<_>, Vec<_>) = endpoints
+        .into_iter()
+        .partition(|e| !circuit_open(&health[&e.name], &settings, now));
+    if closed.is_empty() {
+        // Every circuit is open; try them all rather than fail outright.
+        closed = open;
+    }
+
+    let model = normalize(model);
+    let has_model: Vec<bool> = closed
+        .iter()
+        .map(|e| inventory(e, settings.inventory_ttl, probe).is_none_or(|m| m.contains(&model)))
+        .collect();
+
+    // Strategy rank per endpoint, aligned with `closed`; lower goes first.
+    let ranks: Vec<u64> = match settings.strategy {
+        Strategy::RoundRobin => {
+            let mut state = store::load(ROUND_ROBIN_KEY).unwrap_or_else(|| json!({}));
+            let ranks = round_robin(&closed, &mut state);
+            store::save(ROUND_ROBIN_KEY, &state)?;
+            ranks
+        }
+        Strategy::LeastLoaded => closed
+            .iter()
+            .map(|e| {
+                // Loaded models per unit of weight, scaled to stay integral.
+                running(e, probe).map_or(u64::MAX, |models| models.len() as u64 * 1000 / e.weight)
+            })
+            .collect(),
+        Strategy::ModelAffinity => closed
+            .iter()
+            .map(|e| {
+                let loaded = running(e, probe).is_some_and(|models| models.contains(&model));
+                if loaded {
+                    0
+                } else {
+                    1
+                }
+            })
+            .collect(),
+    };
+    Ok(prioritize(
+        closed
+            .into_iter()
+            .zip(has_model)
+            .zip(ranks)
+            .map(|((e, has), rank)| (e, has, rank))
+            .collect(),
+    ))
+}
+
+/// Order `(endpoint, has_model, rank)` candidates: hosts without the model
+/// go last, then by rank, with ties preferring heavier weights.
+fn prioritize(mut candidates: Vec<(Endpoint, bool, u64)>) -> Vec<Endpoint> {
+    candidates.sort_by(|(a, a_has, a_rank), (b, b_has, b_rank)| {
+        (!a_has, a_rank, b.weight).cmp(&(!b_has, b_rank, a.weight))
+    });
+    candidates.into_iter().map(|(e, _, _)| e).collect()
+}
+
+fn circuit_open(health: &Value, settings: &Settings, now: u64) -> bool {
+    let failures = health["failures"].as_u64().unwrap_or(0);
+    let opened_at = health["last_failure_at"].as_u64().unwrap_or(0);
+    failures >= settings.failure_threshold && now < opened_at + settings.cooldown
+}
+
+/// Smooth weighted round robin over the per-endpoint counters in `state`.
+/// Returns each endpoint's rank: 0 for the chosen one, then the order the
+/// rest would be chosen next.
+fn round_robin(endpoints: &[Endpoint], state: &mut Value) -> Vec<u64> {
+    let total: u64 = endpoints.iter().map(|e| e.weight).sum();
+    let mut current: Vec<i64> = endpoints
+        .iter()
+        .map(|e| state[&e.name].as_i64().unwrap_or(0) + e.weight as i64)
+        .collect();
+    let chosen = (0..endpoints.len())
+        .max_by_key(|&i| (current[i], std::cmp::Reverse(i)))
+        .unwrap_or(0);
+    current[chosen] -= total as i64;
+    for (e, c) in endpoints.iter().zip(&current) {
+        state[&e.name] = json!(c);
+    }
+
+    let mut order: Vec<usize> = (0..endpoints.len()).collect();
+    order.sort_by_key(|&i| (i != chosen, std::cmp::Reverse(current[i])));
+    let mut ranks = vec![0; endpoints.len()];
+    for (rank, i) in order.into_iter().enumerate() {
+        ranks[i] = rank as u64;
+    }
+    ranks
+}
+
+/// Models an endpoint carries, from config or a cached `/api/tags` call.
+fn inventory(endpoint: &Endpoint, ttl: u64, probe: Probe) -> Option<Vec<String>> {
+    if let Some(models) = &endpoint.models {
+        return Some(models.clone());
+    }
+    let key = format!("router:inventory:{}", endpoint.name);
+    let now = store::now();
+    if let Some(cached) = store::load(&key) {
+        if now < cached["fetched_at"].as_u64().unwrap_or(0) + ttl {
+            return names(&cached["models"]);
+        }
+    }
+    let tags = probe(endpoint, "/api/tags")?;
+    let models = names(&tags["models"])?;
+    let _ = store::save(&key, &json!({"fetched_at": now, "models": tags["models"]}));
+    Some(models)
+}
+
+/// Models currently loaded on an endpoint, from `/api/ps`.
+fn running(endpoint: &Endpoint, probe: Probe) -> Option<Vec<String>> {
+    let ps = probe(endpoint, "/api/ps")?;
+    names(&ps["models"])
+}
+
+fn names(models: &Value) -> Option<Vec<String>> {
+    models.as_array().map(|list| {
+        list.iter()
+            .filter_map(|m| m.get("name").or_else(|| m.get("model")))
+            .filter_map(|v| v.as_str())
+            .map(normalize)
+            .collect()
+    })
+}
+
+/// Model names without a tag mean `:latest`.
+fn normalize(name: &str) -> String {
+    if name.contains(':') {
+        name.to_string()
+    } else {
+        format!("{name}:latest")
+    }
+}
+
+pub fn record_success(endpoint: &Endpoint) -> Result<(), OllamaError> {
+    let mut health = store::load(HEALTH_KEY).unwrap_or_else(|| json!({}));
+    if health[&endpoint.name]["failures"].as_u64().unwrap_or(0) == 0 {
+        return Ok(());
+    }
+    let entry = &mut health[&endpoint.name];
+    entry["failures"] = json!(0);
+    entry["last_success_at"] = json!(store::now());
+    store::save(HEALTH_KEY, &health)
+}
+
+pub fn record_failure(endpoint: &Endpoint, error: &OllamaError) -> Result<(), OllamaError> {
+    let mut health = store::load(HEALTH_KEY).unwrap_or_else(|| json!({}));
+    let entry = &mut health[&endpoint.name];
+    entry["failures"] = json!(entry["failures"].as_u64().unwrap_or(0) + 1);
+    entry["last_failure_at"] = json!(store::now());
+    entry["last_error"] = error.to_json();
+    store::save(HEALTH_KEY, &health)
+}
+
+/// Health, circuit state and known inventory of every endpoint.
+pub fn status(config: &Value, default_url: &str) -> Result<Value, OllamaError> {
+    let settings = Settings::resolve(config)?;
+    let health = store::load(HEALTH_KEY).unwrap_or_else(|| json!({}));
+    let now = store::now();
+    let endpoints: Vec<Value> = configured(config, default_url)?
+        .into_iter()
+        .map(|e| {
+            let h = &health[&e.name];
+            let models = e.models.clone().or_else(|| {
+                store::load(&format!("router:inventory:{}", e.name))
+                    .and_then(|cached| names(&cached["models"]))
+            });
+            json!({
+                "name": e.name,
+                "url": e.url,
+                "weight": e.weight,
+                "circuit": if circuit_open(h, &settings, now) { "open" } else { "closed" },
+                "failures": h["failures"].as_u64().unwrap_or(0),
+                "last_error": h.get("last_error"),
+                "models": models
+            })
+        })
+        .collect();
+    Ok(json!({"endpoints": endpoints}))
+}
+
+/// JSON schemas for the `endpoints` and `routing` config entries.
+pub fn schema() -> (Value, Value) {
+    let endpoints = json!({
+        "type": "array",
+        "description": "Ollama hosts to spread requests over; overrides ollama_url",
+        "items": {
+            "type": "object",
+            "required": ["url"],
+            "properties": {
+                "url": {"type": "string"},
+                "name": {"type": "string", "description": "Defaults to the URL"},
+                "weight": {"type": "integer", "default": 1},
+                "auth": crate::auth::schema(),
+                "models": {
+                    "type": "array",
+                    "items": {"type": "string"},
+                    "description": "Models served; discovered via /api/tags when omitted"
+                }
+            }
+        }
+    });
+    let routing = json!({
+        "type": "object",
+        "description": "How requests are spread over endpoints and when hosts are skipped",
+        "properties": {
+            "strategy": {
+                "type": "string",
+                "enum": ["round_robin", "least_loaded", "model_affinity"],
+                "default": "round_robin"
+            },
+            "failure_threshold": {
+                "type": "integer",
+                "description": "Consecutive failures that open an endpoint's circuit",
+                "default": DEFAULT_FAILURE_THRESHOLD
+            },
+            "cooldown_seconds": {
+                "type": "integer",
+                "description": "How long an open circuit skips the endpoint",
+                "default": DEFAULT_COOLDOWN_SECONDS
+            },
+            "inventory_ttl_seconds": {
+                "type": "integer",
+                "description": "How long a discovered model inventory is reused",
+                "default": DEFAULT_INVENTORY_TTL_SECONDS
+            }
+        }
+    });
+    (endpoints, routing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(name: &str, weight: u64, models: &[&str]) -> Endpoint {
+        Endpoint {
+            name: name.to_string(),
+            url: format!("http://{name}:11434"),
+            weight,
+            auth: None,
+            models: Some(models.iter().map(|m| normalize(m)).collect()),
+        }
+    }
+
+    #[test]
+    fn round_robin_alternates_equal_weights() {
+        let endpoints = [endpoint("a", 1, &[]), endpoint("b", 1, &[])];
+        let mut state = json!({});
+        assert_eq!(round_robin(&endpoints, &mut state), vec![0, 1]);
+        assert_eq!(round_robin(&endpoints, &mut state), vec![1, 0]);
+        assert_eq!(round_robin(&endpoints, &mut state), vec![0, 1]);
+    }
+
+    #[test]
+    fn round_robin_honours_weights() {
+        let endpoints = [endpoint("a", 2, &[]), endpoint("b", 1, &[])];
+        let mut state = json!({});
+        let firsts: Vec<usize> = (0..3)
+            .map(|_| {
+                let ranks = round_robin(&endpoints, &mut state);
+                ranks.iter().position(|&r| r == 0).unwrap()
+            })
+            .collect();
+        assert_eq!(firsts.iter().filter(|&&i| i == 0).count(), 2);
+    }
+
+    #[test]
+    fn host_without_model_goes_last_even_when_chosen() {
+        let endpoints = vec![
+            endpoint("a", 1, &["other"]),
+            endpoint("b", 1, &["llama3.2"]),
+        ];
+        let mut state = json!({});
+        let ranks = round_robin(&endpoints, &mut state);
+        assert_eq!(ranks, vec![0, 1], "round robin picks a first");
+
+        let model = normalize("llama3.2");
+        let candidates = endpoints
+            .into_iter()
+            .zip(ranks)
+            .map(|(e, rank)| {
+                let has = e.models.as_ref().is_some_and(|m| m.contains(&model));
+                (e, has, rank)
+            })
+            .collect();
+        let order: Vec<String> = prioritize(candidates).into_iter().map(|e| e.name).collect();
+        assert_eq!(order, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn ties_prefer_heavier_weight() {
+        let candidates = vec![
+            (endpoint("light", 1, &[]), true, 0),
+            (endpoint("heavy", 3, &[]), true, 0),
+        ];
+        let order: Vec<String> = prioritize(candidates).into_iter().map(|e| e.name).collect();
+        assert_eq!(order, vec!["heavy", "light"]);
+    }
+}
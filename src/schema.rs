@@ -0,0 +1,232 @@
+//! Minimal JSON schema validation for structured model output.
+//!
+//! Covers the subset of JSON schema Ollama accepts for `format`: `type`,
+//! `enum`, `const`, `properties`, `required`, `additionalProperties`,
+//! `items`, `anyOf`, and basic length and range bounds.
+
+use serde_json::Value;
+
+/// Validate `value` against `schema`, returning one message per violation.
+pub fn validate(value: &Value, schema: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    check(value, schema, "$", &mut errors);
+    errors
+}
+
+fn check(value: &Value, schema: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+
+    if let Some(ty) = schema.get("type") {
+        let allowed: Vec<&str> = match ty {
+            Value::String(s) => vec![s.as_str()],
+            Value::Array(list) => list.iter().filter_map(|v| v.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|t| matches_type(value, t)) {
+            errors.push(format!(
+                "{path}: expected {}, got {}",
+                allowed.join(" or "),
+                type_name(value)
+            ));
+            return;
+        }
+    }
+
+    if let Some(options) = schema.get("enum").and_then(|v| v.as_array()) {
+        if !options.contains(value) {
+            errors.push(format!("{path}: value not in enum"));
+        }
+    }
+
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            errors.push(format!("{path}: expected constant {expected}"));
+        }
+    }
+
+    if let Some(variants) = schema.get("anyOf").and_then(|v| v.as_array()) {
+        let matched = variants.iter().any(|variant| {
+            let mut sub = Vec::new();
+            check(value, variant, path, &mut sub);
+            sub.is_empty()
+        });
+        if !matched {
+            errors.push(format!("{path}: does not match any of anyOf"));
+        }
+    }
+
+    match value {
+        Value::Object(map) => {
+            let properties = schema.get("properties").and_then(|v| v.as_object());
+            if let Some(required) = schema.get("required").and_then(|v| v.as_array()) {
+                for key in required.iter().filter_map(|v| v.as_str()) {
+                    if !map.contains_key(key) {
+                        errors.push(format!("{path}: missing required property '{key}'"));
+                    }
+                }
+            }
+            for (key, item) in map {
+                let child = format!("{path}.{key}");
+                match properties.and_then(|p| p.get(key)) {
+                    Some(prop) => check(item, prop, &child, errors),
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => {
+                            errors.push(format!("{path}: unexpected property '{key}'"));
+                        }
+                        Some(extra @ Value::Object(_)) => check(item, extra, &child, errors),
+                        _ => {}
+                    },
+                }
+            }
+        }
+        Value::Array(list) => {
+            if let Some(min) = schema.get("minItems").and_then(|v| v.as_u64()) {
+                if (list.len() as u64) < min {
+                    errors.push(format!("{path}: expected at least {min} items"));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(|v| v.as_u64()) {
+                if list.len() as u64 > max {
+                    errors.push(format!("{path}: expected at most {max} items"));
+                }
+            }
+            if let Some(items) = schema.get("items") {
+                for (i, item) in list.iter().enumerate() {
+                    check(item, items, &format!("{path}[{i}]"), errors);
+                }
+            }
+        }
+        Value::String(s) => {
+            let len = s.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(|v| v.as_u64()) {
+                if len < min {
+                    errors.push(format!("{path}: shorter than {min} characters"));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(|v| v.as_u64()) {
+                if len > max {
+                    errors.push(format!("{path}: longer than {max} characters"));
+                }
+            }
+        }
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or(0.0);
+            if let Some(min) = schema.get("minimum").and_then(|v| v.as_f64()) {
+                if n < min {
+                    errors.push(format!("{path}: below minimum {min}"));
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(|v| v.as_f64()) {
+                if n > max {
+                    errors.push(format!("{path}: above maximum {max}"));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn matches_type(value: &Value, ty: &str) -> bool {
+    match ty {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn person() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string", "minLength": 1},
+                "age": {"type": "integer", "minimum": 0},
+                "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 2}
+            },
+            "required": ["name", "age"],
+            "additionalProperties": false
+        })
+    }
+
+    #[test]
+    fn accepts_a_matching_value() {
+        let value = json!({"name": "Ada", "age": 36, "tags": ["math"]});
+        assert!(validate(&value, &person()).is_empty());
+    }
+
+    #[test]
+    fn reports_each_violation_with_its_path() {
+        let value = json!({"name": "", "age": -1, "tags": ["a", 2, "c"], "extra": true});
+        let errors = validate(&value, &person());
+        assert!(errors.contains(&"$.name: shorter than 1 characters".to_string()));
+        assert!(errors.contains(&"$.age: below minimum 0".to_string()));
+        assert!(errors.contains(&"$.tags: expected at most 2 items".to_string()));
+        assert!(errors.contains(&"$.tags[1]: expected string, got number".to_string()));
+        assert!(errors.contains(&"$: unexpected property 'extra'".to_string()));
+        assert_eq!(errors.len(), 5);
+    }
+
+    #[test]
+    fn reports_missing_required_properties() {
+        let errors = validate(&json!({"name": "Ada"}), &person());
+        assert_eq!(errors, vec!["$: missing required property 'age'"]);
+    }
+
+    #[test]
+    fn type_mismatch_stops_at_the_node() {
+        let errors = validate(&json!("not an object"), &person());
+        assert_eq!(errors, vec!["$: expected object, got string"]);
+    }
+
+    #[test]
+    fn checks_enum_const_and_any_of() {
+        let schema = json!({"enum": ["red", "green"]});
+        assert!(validate(&json!("red"), &schema).is_empty());
+        assert_eq!(
+            validate(&json!("blue"), &schema),
+            vec!["$: value not in enum"]
+        );
+
+        let schema = json!({"const": 1});
+        assert_eq!(validate(&json!(2), &schema), vec!["$: expected constant 1"]);
+
+        let schema = json!({"anyOf": [{"type": "string"}, {"type": "null"}]});
+        assert!(validate(&Value::Null, &schema).is_empty());
+        assert_eq!(
+            validate(&json!(3), &schema),
+            vec!["$: does not match any of anyOf"]
+        );
+    }
+
+    #[test]
+    fn integer_rejects_fractions() {
+        let schema = json!({"type": ["integer", "null"]});
+        assert!(validate(&json!(3), &schema).is_empty());
+        assert_eq!(
+            validate(&json!(3.5), &schema),
+            vec!["$: expected integer or null, got number"]
+        );
+    }
+}
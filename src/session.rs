@@ -0,0 +1,150 @@
+//! Multi-turn conversation sessions kept in the persistent variable store.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::context;
+use crate::error::OllamaError;
+use crate::store;
+
+/// Oldest turns beyond this are dropped when a session is saved.
+const MAX_STORED_MESSAGES: usize = 200;
+/// Kept outside the `session:` namespace so no session id can shadow it.
+const COUNTER_KEY: &str = "session_counter";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub system: Option<String>,
+    /// Compacted summary of turns removed from `messages`.
+    #[serde(default)]
+    pub summary: Option<String>,
+    pub messages: Vec<Value>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl Session {
+    pub fn new(id: &str, system: Option<&str>) -> Self {
+        let now = store::now();
+        Self {
+            id: id.to_string(),
+            system: system.map(str::to_string),
+            summary: None,
+            messages: Vec::new(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn load(id: &str) -> Option<Self> {
+        store::load(&key(id)).and_then(|v| serde_json::from_value(v).ok())
+    }
+
+    /// Load `id`, creating an empty session with `system` if it is missing.
+    pub fn load_or_create(id: &str, system: Option<&str>) -> Self {
+        Self::load(id).unwrap_or_else(|| Self::new(id, system))
+    }
+
+    pub fn save(&mut self) -> Result<(), OllamaError> {
+        if self.messages.len() > MAX_STORED_MESSAGES {
+            let excess = self.messages.len() - MAX_STORED_MESSAGES;
+            self.messages.drain(..excess);
+        }
+        self.updated_at = store::now();
+        store::save(&key(&self.id), &serde_json::to_value(&*self)?)
+    }
+
+    pub fn delete(id: &str) -> Result<(), OllamaError> {
+        store::remove(&key(id))
+    }
+
+    /// Full message list to send: system prompt, stored history, new turns.
+    pub fn context(&self, turns: &[Value]) -> Vec<Value> {
+        let mut messages = Vec::with_capacity(self.messages.len() + turns.len() + 1);
+        let has_system = turns
+            .iter()
+            .chain(&self.messages)
+            .any(|m| m.get("role").and_then(|v| v.as_str()) == Some("system"));
+        if let (Some(system), false) = (&self.system, has_system) {
+            messages.push(json!({"role": "system", "content": system}));
+        }
+        if let Some(summary) = &self.summary {
+            messages.push(context::summary_message(summary));
+        }
+        messages.extend(self.messages.iter().cloned());
+        messages.extend(turns.iter().cloned());
+        messages
+    }
+
+    /// Replace the oldest `dropped` stored turns with `summary`.
+    pub fn compact(&mut self, summary: String, dropped: usize) {
+        let dropped = dropped.min(self.messages.len());
+        self.messages.drain(..dropped);
+        self.summary = Some(summary);
+    }
+
+    /// Forget the conversation, including any summary of earlier turns.
+    pub fn reset(&mut self) {
+        self.messages.clear();
+        self.summary = None;
+    }
+
+    /// Append the caller's turns and the assistant reply to the history.
+    pub fn record(&mut self, turns: &[Value], reply: Value) {
+        self.messages.extend(turns.iter().cloned());
+        self.messages.push(reply);
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "session_id": self.id,
+            "system": self.system,
+            "summary": self.summary,
+            "messages": self.messages,
+            "message_count": self.messages.len(),
+            "created_at": self.created_at,
+            "updated_at": self.updated_at
+        })
+    }
+}
+
+fn key(id: &str) -> String {
+    format!("session:{id}")
+}
+
+/// Generate a session id unlikely to collide with existing ones.
+pub fn new_id() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let counter = store::load(COUNTER_KEY)
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0)
+        + 1;
+    let _ = store::save(COUNTER_KEY, &json!(counter));
+    format!("s-{nanos:x}-{counter}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_forgets_the_summary() {
+        let mut session = Session::new("s1", Some("Be brief."));
+        let hi = json!({"role": "user", "content": "hi"});
+        let hello = json!({"role": "assistant", "content": "hello"});
+        session.record(&[hi], hello);
+        session.compact("They greeted each other.".to_string(), 2);
+        assert!(session.context(&[]).iter().any(context::is_summary));
+
+        session.reset();
+        let again = json!({"role": "user", "content": "hi again"});
+        assert_eq!(
+            session.context(std::slice::from_ref(&again)),
+            vec![json!({"role": "system", "content": "Be brief."}), again]
+        );
+    }
+}
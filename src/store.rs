@@ -0,0 +1,34 @@
+//! JSON values in the plugin's persistent variable store.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use extism_pdk::var;
+use serde_json::Value;
+
+use crate::error::{Category, OllamaError};
+
+pub fn load(key: &str) -> Option<Value> {
+    var::get::<String>(key)
+        .ok()
+        .flatten()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+}
+
+pub fn save(key: &str, value: &Value) -> Result<(), OllamaError> {
+    let raw = serde_json::to_string(value)?;
+    var::set(key, raw)
+        .map_err(|e| OllamaError::new(Category::ServerError, format!("storage write failed: {e}")))
+}
+
+pub fn remove(key: &str) -> Result<(), OllamaError> {
+    var::remove(key)
+        .map_err(|e| OllamaError::new(Category::ServerError, format!("storage delete failed: {e}")))
+}
+
+/// Seconds since the Unix epoch, or 0 when the host exposes no clock.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}